@@ -0,0 +1,83 @@
+use crate::languages::Span;
+use crate::theme::Theme;
+use std::io::Write;
+
+const RESET: &str = "\x1b[0m";
+
+/// Outputs the code with ANSI escape sequences for the theme styles of the spans, like a syntax-highlighting
+/// `cat`. Styles are reset before each line break, so every line can be printed on its own.
+pub fn process_ansi<W>(spans: &[Span], code: &str, theme: &Theme, color_depth: &str, writer: &mut W)
+where
+    W: Write,
+{
+    let mut output = String::new();
+    let mut position = 0;
+    for span in spans {
+        output.push_str(&code[position..span.range.start]);
+        let style_start = theme
+            .style(span.name)
+            .map(|style| style.ansi_start(color_depth))
+            .unwrap_or_default();
+        if style_start.is_empty() {
+            output.push_str(&code[span.range.clone()]);
+        } else {
+            let lines: Vec<&str> = code[span.range.clone()].split('\n').collect();
+            for (index, line) in lines.iter().enumerate() {
+                if index > 0 {
+                    output.push('\n');
+                }
+                if !line.is_empty() {
+                    output.push_str(&format!("{}{}{}", style_start, line, RESET));
+                }
+            }
+        }
+        position = span.range.end;
+    }
+    output.push_str(&code[position..]);
+    write!(writer, "{}", output).expect("write should succeed");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args;
+
+    fn run_ansi(code: &str, highlights: &str, extra_args: Vec<&str>) -> String {
+        let args = [
+            vec!["--highlights", highlights, "--format", "ansi"],
+            extra_args,
+        ]
+        .concat();
+        run_with_args(code, "rust", args)
+    }
+
+    #[test]
+    fn test_ansi_nested_captures() {
+        let highlights =
+            r#"(string_literal) @string (escape_sequence) @string.escape "let" @keyword"#;
+        let theme = std::env::temp_dir().join("tree-sitter-cli-via-rust-test-theme.json");
+        std::fs::write(
+            &theme,
+            r##"{"string": "#00ff00", "string.escape": {"color": "red", "bold": true}, "keyword": 5}"##,
+        )
+        .expect("Theme file should be writable");
+        let output = run_ansi(
+            "let x = \"a\\nb\";",
+            highlights,
+            vec!["--theme", theme.to_str().unwrap(), "--color-depth", "256"],
+        );
+        assert_eq!(
+            output,
+            "\x1b[35mlet\x1b[0m x = \x1b[38;5;46m\"a\x1b[0m\x1b[1;31m\\n\x1b[0m\x1b[38;5;46mb\"\x1b[0m;"
+        );
+        std::fs::remove_file(theme).expect("Theme file should be removable");
+    }
+
+    #[test]
+    fn test_ansi_multiline_and_default_theme() {
+        let output = run_ansi("/* a\nb */ fn", "(block_comment) @comment", vec![]);
+        assert_eq!(
+            output,
+            "\x1b[3;38;2;127;132;142m/* a\x1b[0m\n\x1b[3;38;2;127;132;142mb */\x1b[0m fn"
+        );
+    }
+}
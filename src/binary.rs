@@ -0,0 +1,183 @@
+//! The `--format binary` output, which is much faster to read in Emacs Lisp than thousands of text lines.
+//!
+//! All numbers are unsigned LEB128 varints:
+//!
+//! 1. the magic bytes `TSCB` and the format version byte `1`
+//! 2. the count of capture names, then each capture name as byte length and UTF-8 bytes
+//! 3. the count of records, then each record as capture ID (the index into the capture names), start byte and
+//!    byte length
+//!
+//! The records are in the same order as the lines of the default `--format captures`.
+
+use crate::languages::Capture;
+use std::io::Write;
+
+const MAGIC: &[u8; 4] = b"TSCB";
+const VERSION: u8 = 1;
+
+fn write_varint(output: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+pub fn encode(captures: &[Capture]) -> Vec<u8> {
+    let mut capture_names: Vec<&str> = Vec::new();
+    let mut records = Vec::new();
+    for capture in captures {
+        let capture_id = match capture_names.iter().position(|name| *name == capture.name) {
+            Some(capture_id) => capture_id,
+            None => {
+                capture_names.push(capture.name);
+                capture_names.len() - 1
+            }
+        };
+        records.push((capture_id, capture.range.clone()));
+    }
+
+    let mut output = MAGIC.to_vec();
+    output.push(VERSION);
+    write_varint(&mut output, capture_names.len());
+    for name in capture_names {
+        write_varint(&mut output, name.len());
+        output.extend_from_slice(name.as_bytes());
+    }
+    write_varint(&mut output, records.len());
+    for (capture_id, range) in records {
+        write_varint(&mut output, capture_id);
+        write_varint(&mut output, range.start);
+        write_varint(&mut output, range.len());
+    }
+    output
+}
+
+#[cfg(test)]
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+#[cfg(test)]
+impl Reader<'_> {
+    fn take(&mut self, length: usize) -> Result<&[u8], String> {
+        let end = self
+            .position
+            .checked_add(length)
+            .ok_or_else(|| format!("length {} too large at byte {}", length, self.position))?;
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| format!("unexpected end of input at byte {}", self.position))?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<usize, String> {
+        let mut value = 0usize;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(format!("varint too long at byte {}", self.position))
+    }
+}
+
+/// Decodes `--format binary` into `(capture name, start byte, end byte)` tuples. This is the reference
+/// implementation of the format for other decoders, the CLI itself only encodes.
+#[cfg(test)]
+pub fn decode(bytes: &[u8]) -> Result<Vec<(String, usize, usize)>, String> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("missing TSCB magic bytes".to_string());
+    }
+    let version = reader.take(1)?[0];
+    if version != VERSION {
+        return Err(format!("unsupported version {}", version));
+    }
+    let mut capture_names = Vec::new();
+    for _ in 0..reader.varint()? {
+        let length = reader.varint()?;
+        let name = std::str::from_utf8(reader.take(length)?).map_err(|e| e.to_string())?;
+        capture_names.push(name.to_string());
+    }
+    let mut records = Vec::new();
+    for _ in 0..reader.varint()? {
+        let capture_id = reader.varint()?;
+        let start = reader.varint()?;
+        let length = reader.varint()?;
+        let name = capture_names
+            .get(capture_id)
+            .ok_or_else(|| format!("unknown capture ID {}", capture_id))?;
+        let end = start
+            .checked_add(length)
+            .ok_or_else(|| format!("record length {} too large", length))?;
+        records.push((name.clone(), start, end));
+    }
+    Ok(records)
+}
+
+pub fn process_binary<W>(captures: &[Capture], writer: &mut W)
+where
+    W: Write,
+{
+    writer
+        .write_all(&encode(captures))
+        .expect("write should succeed");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args_raw;
+
+    fn run_format(code: &str, format: &str) -> Vec<u8> {
+        let args = vec![
+            "--highlights",
+            tree_sitter_rust::HIGHLIGHTS_QUERY,
+            "--format",
+            format,
+        ];
+        run_with_args_raw(code, "rust", args)
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let code = "// 😄 comment\nfn main() {\n    let x = \"a\\nb\";\n}";
+        let text = String::from_utf8(run_format(code, "captures")).expect("Output should be UTF-8");
+        let binary = run_format(code, "binary");
+        assert!(binary.starts_with(b"TSCB\x01"));
+        let decoded: String = super::decode(&binary)
+            .expect("Binary output should be decodable")
+            .iter()
+            .map(|(name, start, end)| format!("{} {} {}\n", name, start, end))
+            .collect();
+        assert!(!text.is_empty());
+        assert_eq!(decoded, text);
+        assert!(binary.len() < text.len());
+    }
+
+    #[test]
+    fn test_binary_encoding() {
+        let binary = run_format(&format!("{}fn", " ".repeat(200)), "binary");
+        // one capture name "keyword", one record with the start 200 as two byte varint
+        assert_eq!(
+            binary,
+            b"TSCB\x01\x01\x07keyword\x01\x00\xc8\x01\x02".to_vec()
+        );
+        assert!(super::decode(&binary[..binary.len() - 1]).is_err());
+        assert!(super::decode(b"XXXX\x01\x00\x00").is_err());
+        // a name length and a record length which overflow
+        let huge = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01";
+        assert!(super::decode(&[b"TSCB\x01\x01".as_slice(), huge].concat()).is_err());
+        let record = [b"TSCB\x01\x01\x01a\x01\x00\x01".as_slice(), huge].concat();
+        assert!(super::decode(&record).is_err());
+    }
+}
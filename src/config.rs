@@ -0,0 +1,221 @@
+use crate::theme::Theme;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+/// Renames capture names, globally and per language, so the same construct gets the same capture name (and
+/// Emacs face) across grammars.
+#[derive(Default)]
+pub struct CaptureMap {
+    all: HashMap<String, String>,
+    by_language: HashMap<String, HashMap<String, String>>,
+}
+
+/// The `--config` file:
+///
+/// ```toml
+/// # for all languages
+/// [captures]
+/// "constant.builtin" = "constant"
+///
+/// # only for Kotlin, these take precedence
+/// [languages.kotlin.captures]
+/// void = "variable"
+///
+/// # optional, in the same format as the --theme file
+/// [theme]
+/// keyword = "#c678dd"
+///
+/// # the directory with highlights.scm and other queries per language, relative to the config file
+/// [queries]
+/// kotlin = "queries/kotlin"
+/// ```
+#[derive(Default)]
+pub struct Config {
+    pub capture_map: CaptureMap,
+    pub theme: Option<Theme>,
+    pub query_dirs: HashMap<String, PathBuf>,
+}
+
+/// Unquoted dotted capture names like `keyword.function = "statement"` are nested tables in TOML, so they are
+/// joined back into a single capture name.
+fn collect_mapping(
+    table: &Table,
+    prefix: &str,
+    path: &str,
+    mapping: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    for (key, value) in table {
+        let from = format!("{}{}", prefix, key);
+        match value {
+            Value::String(to) => {
+                mapping.insert(from, to.clone());
+            }
+            Value::Table(nested) => collect_mapping(nested, &format!("{}.", from), path, mapping)?,
+            _ => {
+                return Err(format!(
+                    "{}.\"{}\" needs to be a capture name string",
+                    path, from
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_mapping(table: &Table, path: &str) -> Result<HashMap<String, String>, String> {
+    let mut mapping = HashMap::new();
+    collect_mapping(table, "", path, &mut mapping)?;
+    Ok(mapping)
+}
+
+impl Config {
+    pub fn parse(toml: &str) -> Result<Config, String> {
+        let table: Table = toml.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        let mut config = Config::default();
+        if let Some(captures) = table.get("captures") {
+            let Value::Table(captures) = captures else {
+                return Err("captures needs to be a table".to_string());
+            };
+            config.capture_map.all = parse_mapping(captures, "captures")?;
+        }
+        if let Some(languages) = table.get("languages") {
+            let Value::Table(languages) = languages else {
+                return Err("languages needs to be a table".to_string());
+            };
+            for (language, settings) in languages {
+                let Some(captures) = settings.get("captures") else {
+                    continue;
+                };
+                let path = format!("languages.{}.captures", language);
+                let Value::Table(captures) = captures else {
+                    return Err(format!("{} needs to be a table", path));
+                };
+                let mapping = parse_mapping(captures, &path)?;
+                config
+                    .capture_map
+                    .by_language
+                    .insert(language.clone(), mapping);
+            }
+        }
+        if let Some(queries) = table.get("queries") {
+            let Value::Table(queries) = queries else {
+                return Err("queries needs to be a table".to_string());
+            };
+            for (language, dir) in queries {
+                let Value::String(dir) = dir else {
+                    return Err(format!("queries.{} needs to be a directory path", language));
+                };
+                config
+                    .query_dirs
+                    .insert(language.clone(), PathBuf::from(dir));
+            }
+        }
+        if let Some(theme) = table.get("theme") {
+            let theme = serde_json::to_value(theme).map_err(|e| e.to_string())?;
+            config.theme = Some(Theme::from_json(theme).map_err(|e| format!("theme: {}", e))?);
+        }
+        Ok(config)
+    }
+}
+
+impl CaptureMap {
+    /// Maps the capture name for the language, where `None` means the capture is dropped because it was mapped
+    /// to an empty string.
+    ///
+    /// Without an exact mapping, the parents are tried like `keyword.function` with `keyword`, whose target is
+    /// used as it is: `keyword = "font-lock-keyword-face"` maps `keyword.function` to `font-lock-keyword-face`.
+    pub fn map(&self, language: &str, capture_name: &str) -> Option<String> {
+        let mappings = [self.by_language.get(language), Some(&self.all)];
+        let mut prefix = capture_name;
+        loop {
+            for mapping in mappings.iter().flatten() {
+                if let Some(to) = mapping.get(prefix) {
+                    if to.is_empty() {
+                        return None;
+                    }
+                    return Some(to.clone());
+                }
+            }
+            match prefix.rfind('.') {
+                Some(index) => prefix = &prefix[..index],
+                None => return Some(capture_name.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args;
+
+    fn run_with_config(config: &str, test_name: &str, extra_args: Vec<&str>) -> String {
+        let config_path =
+            std::env::temp_dir().join(format!("tree-sitter-cli-via-rust-{}.toml", test_name));
+        std::fs::write(&config_path, config).expect("Config file should be writable");
+        let args = [
+            vec![
+                "--highlights",
+                r#""def" @keyword.function "return" @keyword.return (none) @constant.builtin (identifier) @function"#,
+                "--config",
+                config_path.to_str().unwrap(),
+            ],
+            extra_args,
+        ]
+        .concat();
+        let output = run_with_args("def f(): return None", "python", args);
+        std::fs::remove_file(config_path).expect("Config file should be removable");
+        output
+    }
+
+    #[test]
+    fn test_capture_mapping() {
+        let config = r#"
+[captures]
+keyword = "statement"
+"constant.builtin" = "constant"
+
+[languages.python.captures]
+"keyword.return" = "control"
+function = ""
+
+[languages.rust.captures]
+"constant.builtin" = "ignored for python"
+"#;
+        assert_eq!(
+            run_with_config(config, "mapping", vec![]),
+            "statement 0 3\ncontrol 9 15\nconstant 16 20\n"
+        );
+    }
+
+    #[test]
+    fn test_config_theme() {
+        let config = r##"
+[languages.python.captures]
+"keyword.return" = "keyword"
+
+[theme]
+keyword = "#ff0000"
+"##;
+        let output = run_with_config(config, "theme", vec!["--format", "ansi"]);
+        assert!(output.starts_with("\x1b[38;2;255;0;0mdef\x1b[0m "));
+        assert!(output.contains(" \x1b[38;2;255;0;0mreturn\x1b[0m "));
+    }
+
+    #[test]
+    fn test_dotted_capture_names() {
+        // unquoted dotted keys are capture names, not languages, and languages are only read from [languages]
+        let config = r#"
+[captures]
+constant.builtin = "constant"
+python.keyword = "not a language"
+
+[languages.python.captures]
+keyword.return = "control"
+"#;
+        assert_eq!(
+            run_with_config(config, "dotted", vec![]),
+            "keyword.function 0 3\nfunction 4 5\ncontrol 9 15\nconstant 16 20\n"
+        );
+    }
+}
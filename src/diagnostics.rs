@@ -0,0 +1,115 @@
+use crate::json::range_to_json;
+use crate::text::truncate;
+use serde_json::{json, Value};
+use std::io::Write;
+use tree_sitter::{Node, Tree};
+
+const MAX_TEXT_LENGTH: usize = 40;
+
+fn error_to_json(node: Node, code: &str) -> Value {
+    let text = &code[node.byte_range()];
+    let message = if text.trim().is_empty() {
+        "Syntax error".to_string()
+    } else {
+        // only the first line, since ERROR nodes can span whole blocks
+        let first_line = text.trim().lines().next().unwrap_or_default();
+        format!("Unexpected `{}`", truncate(first_line, MAX_TEXT_LENGTH))
+    };
+    json!({
+        "type": "error",
+        "kind": node.kind(),
+        "text": truncate(text, MAX_TEXT_LENGTH),
+        "range": range_to_json(node.range()),
+        "message": message,
+    })
+}
+
+fn missing_to_json(node: Node) -> Value {
+    let message = if node.is_named() {
+        format!("Missing {}", node.kind())
+    } else {
+        format!("Missing `{}`", node.kind())
+    };
+    json!({
+        "type": "missing",
+        "kind": node.kind(),
+        "text": "",
+        "range": range_to_json(node.range()),
+        "message": message,
+    })
+}
+
+fn collect_diagnostics(node: Node, code: &str, diagnostics: &mut Vec<Value>) {
+    if node.is_error() {
+        // nested errors and missing nodes are part of the outer error
+        diagnostics.push(error_to_json(node, code));
+        return;
+    }
+    if node.is_missing() {
+        diagnostics.push(missing_to_json(node));
+        return;
+    }
+    if !node.has_error() {
+        return;
+    }
+    for child in node.children(&mut node.walk()) {
+        collect_diagnostics(child, code, diagnostics);
+    }
+}
+
+/// Outputs a JSON array of the `ERROR` and `MISSING` nodes of the tree in document order, which is empty for
+/// code without syntax errors.
+///
+/// Each diagnostic has `type` (`error` or `missing`), `kind` (`ERROR` or the kind of the missing node), `text`
+/// (the unexpected text), `range` and a human-readable `message` like ``Missing `;` ``.
+pub fn process_diagnostics<W>(tree: &Tree, code: &str, writer: &mut W)
+where
+    W: Write,
+{
+    let mut diagnostics = Vec::new();
+    collect_diagnostics(tree.root_node(), code, &mut diagnostics);
+    writeln!(writer, "{}", Value::Array(diagnostics)).expect("write should succeed");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args;
+    use serde_json::Value;
+
+    fn run_diagnostics(code: &str, language: &str) -> Value {
+        let output = run_with_args(code, language, vec!["--mode", "diagnostics"]);
+        serde_json::from_str(&output).expect("Output should be valid JSON")
+    }
+
+    #[test]
+    fn test_diagnostics_valid_code() {
+        assert_eq!(
+            run_diagnostics("let x = 1;", "javascript"),
+            Value::Array(vec![])
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_missing() {
+        let diagnostics = run_diagnostics("fn main() {\n    let x = 1\n}", "rust");
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(diagnostics[0]["type"], "missing");
+        assert_eq!(diagnostics[0]["kind"], ";");
+        assert_eq!(diagnostics[0]["message"], "Missing `;`");
+        assert_eq!(diagnostics[0]["range"]["start"], 25);
+        assert_eq!(diagnostics[0]["range"]["end"], 25);
+        assert_eq!(diagnostics[0]["range"]["start_point"]["row"], 1);
+        assert_eq!(diagnostics[0]["range"]["start_point"]["column"], 13);
+    }
+
+    #[test]
+    fn test_diagnostics_error() {
+        let diagnostics = run_diagnostics("x = 1\n)\ny = 2", "python");
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(diagnostics[0]["type"], "error");
+        assert_eq!(diagnostics[0]["kind"], "ERROR");
+        assert_eq!(diagnostics[0]["text"], ")");
+        assert_eq!(diagnostics[0]["message"], "Unexpected `)`");
+        assert_eq!(diagnostics[0]["range"]["start_point"]["row"], 1);
+    }
+}
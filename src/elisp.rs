@@ -0,0 +1,110 @@
+use crate::languages::Capture;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Escapes a string for the Emacs Lisp reader, which allows raw newlines in strings.
+pub fn string_to_elisp(string: &str) -> String {
+    format!("\"{}\"", string.replace("\\", "\\\\").replace("\"", "\\\""))
+}
+
+/// Escapes a symbol name like a capture name for the Emacs Lisp reader.
+pub fn symbol_to_elisp(name: &str) -> String {
+    let mut symbol = String::new();
+    for character in name.chars() {
+        if character.is_whitespace() || "()[]\"';#`,?\\".contains(character) {
+            symbol.push('\\');
+        }
+        symbol.push(character);
+    }
+    symbol
+}
+
+/// Converts JSON into a form for `read` in Emacs: objects become plists with kebab-case keywords like
+/// `:start-point`, arrays become lists, `true` is `t` and both `false` and `null` are `nil`.
+pub fn json_to_elisp(value: &Value) -> String {
+    match value {
+        Value::Null | Value::Bool(false) => "nil".to_string(),
+        Value::Bool(true) => "t".to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::String(string) => string_to_elisp(string),
+        Value::Array(values) => format!(
+            "({})",
+            values
+                .iter()
+                .map(json_to_elisp)
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+        Value::Object(map) => format!(
+            "({})",
+            map.iter()
+                .map(|(key, value)| format!(":{} {}", key.replace('_', "-"), json_to_elisp(value)))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+    }
+}
+
+/// Outputs a vector of `(capture-name start end)` lists for `read` in Emacs, in the same order as the default
+/// `--format captures`.
+///
+/// The positions are 1-based character positions like Emacs buffer positions instead of byte offsets, so they can
+/// be passed directly to `put-text-property`.
+pub fn process_elisp<W>(captures: &[Capture], code: &str, writer: &mut W)
+where
+    W: Write,
+{
+    // the character positions of the capture offsets, counted in one pass over the sorted offsets
+    let mut offsets: Vec<usize> = captures
+        .iter()
+        .flat_map(|capture| [capture.range.start, capture.range.end])
+        .collect();
+    offsets.sort_unstable();
+    offsets.dedup();
+    let mut positions = HashMap::with_capacity(offsets.len());
+    let (mut byte, mut position) = (0, 1);
+    for offset in offsets {
+        position += code[byte..offset].chars().count();
+        byte = offset;
+        positions.insert(offset, position);
+    }
+
+    let lists: Vec<String> = captures
+        .iter()
+        .map(|capture| {
+            format!(
+                "({} {} {})",
+                symbol_to_elisp(capture.name),
+                positions[&capture.range.start],
+                positions[&capture.range.end]
+            )
+        })
+        .collect();
+    writeln!(writer, "[{}]", lists.join(" ")).expect("write should succeed");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args;
+
+    fn run_elisp(highlights: &str) -> String {
+        let args = vec!["--highlights", highlights, "--format", "elisp"];
+        run_with_args("x = \"😄ä\" # c", "python", args)
+    }
+
+    #[test]
+    fn test_elisp_format() {
+        // the emoji and ä are one character each, but 4 and 2 bytes
+        assert_eq!(
+            run_elisp("(identifier) @variable (string) @string (comment) @comment.line"),
+            "[(variable 1 2) (string 5 9) (comment.line 10 13)]\n"
+        );
+        // nested captures share offsets and are not in the order of their ends
+        assert_eq!(
+            run_elisp("(expression_statement) @statement (string) @string"),
+            "[(statement 1 9) (string 5 9)]\n"
+        );
+        assert_eq!(super::symbol_to_elisp("odd name?"), "odd\\ name\\?");
+    }
+}
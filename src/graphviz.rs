@@ -1,66 +1,623 @@
 use std::collections::HashMap;
+use std::io::{ErrorKind, Write};
+use std::ops::Range;
+use std::process::{Command, Stdio};
 use tree_sitter::{Node, Tree};
 
-pub fn generate_dot_graph(tree: &Tree, code: &String) -> String {
-    fn escape_string(string: &str) -> String {
-        string
-            .replace("\\", "\\\\")
-            .replace("\"", "\\\"")
-            .replace("\n", "\\n")
-            .replace("\r", "\\r")
-            .replace("\t", "\\t")
-            .replace("\x08", "\\b") // backspace
-            .replace("\x0c", "\\f") // form feed
+use crate::navigation::field_name;
+use crate::text::truncate;
+
+pub static RENDER_FORMATS: [&str; 3] = ["svg", "png", "pdf"];
+pub static RANKDIRS: [&str; 4] = ["TB", "LR", "BT", "RL"];
+
+/// The node label template when `--graphviz-label` is not passed.
+pub const DEFAULT_LABEL_TEMPLATE: &str = "{kind} {range}\n{text}";
+/// The node text is truncated to this many characters when `--graphviz-max-text` is not passed.
+pub const DEFAULT_MAX_TEXT_LENGTH: usize = 60;
+
+#[derive(Default)]
+pub struct GraphvizOptions {
+    /// Render anonymous nodes like punctuation grey and dashed, so the named nodes of the grammar stand out.
+    pub style_anonymous: bool,
+    /// Omit anonymous nodes like punctuation.
+    pub named_only: bool,
+    /// Nodes deeper than this, relative to the graph root, are collapsed into a summary node.
+    pub max_depth: Option<usize>,
+    /// Root the graph at the smallest node covering this byte range instead of the tree root.
+    pub focus: Option<Range<usize>>,
+    /// Color `ERROR` nodes red, draw `MISSING` nodes dashed, outline the nodes containing errors and add a
+    /// legend for these.
+    pub highlight_errors: bool,
+    /// The capture names of the highlights query by node ID. When set, captured nodes are annotated and filled
+    /// with a color per capture name, and all other nodes are greyed out.
+    pub captures: Option<HashMap<usize, Vec<String>>>,
+    /// The graph direction like `LR` for left to right, Graphviz defaults to `TB` (top to bottom).
+    pub rankdir: Option<String>,
+    /// The node label with the placeholders `{kind}`, `{range}`, `{start}`, `{end}`, `{point}`, `{text}` and
+    /// `{field}`. Defaults to `DEFAULT_LABEL_TEMPLATE`.
+    pub label_template: Option<String>,
+    /// The node text is truncated to this many characters, defaults to `DEFAULT_MAX_TEXT_LENGTH`.
+    pub max_text_length: Option<usize>,
+    /// Replace runs of whitespace like newlines and indentation in the node text with a single space.
+    pub collapse_whitespace: bool,
+}
+
+const CAPTURE_COLORS: [&str; 12] = [
+    "#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462", "#b3de69", "#fccde5",
+    "#bc80bd", "#ccebc5", "#ffed6f", "#a6cee3",
+];
+
+/// A color per capture name which is stable across runs, unlike the randomly seeded `HashMap` hasher.
+fn capture_color(capture_name: &str) -> &'static str {
+    let hash = capture_name.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte as usize)
+    });
+    CAPTURE_COLORS[hash % CAPTURE_COLORS.len()]
+}
+
+fn capture_legend(captures: &HashMap<usize, Vec<String>>) -> String {
+    let mut capture_names: Vec<&String> = captures.values().flatten().collect();
+    capture_names.sort();
+    capture_names.dedup();
+    let mut legend = String::from("subgraph cluster_captures {\nlabel=\"Captures\";\n");
+    for (index, capture_name) in capture_names.iter().enumerate() {
+        legend.push_str(&format!(
+            "capture_{}[label=\"@{}\", style=\"filled\", fillcolor=\"{}\"];\n",
+            index,
+            capture_name.replace("\"", "\\\""),
+            capture_color(capture_name)
+        ));
+    }
+    legend.push_str("}\n");
+    legend
+}
+
+const ERROR_LEGEND: &str = r##"subgraph cluster_legend {
+label="Legend";
+legend_error[label="ERROR", style=filled, color=red, fillcolor="#ffcccc"];
+legend_missing[label="MISSING", style=dashed, color=red];
+legend_has_error[label="contains errors", color=orange];
+}
+"##;
+
+/// The dot attributes after the label, like `, style=dashed, color=gray50`.
+fn node_attributes(node: Node, options: &GraphvizOptions) -> String {
+    let mut styles = Vec::new();
+    let mut color = None;
+    let mut extra = Vec::new();
+    if options.style_anonymous && !node.is_named() {
+        styles.push("dashed");
+        color = Some("gray50");
+        extra.push("fontcolor=gray50".to_string());
+    }
+    if let Some(captures) = &options.captures {
+        match captures.get(&node.id()) {
+            Some(capture_names) => {
+                styles.push("filled");
+                extra.push(format!(
+                    "fillcolor=\"{}\"",
+                    capture_color(&capture_names[0])
+                ));
+            }
+            None => {
+                color = Some("gray70");
+                extra.push("fontcolor=gray60".to_string());
+            }
+        }
+    }
+    if options.highlight_errors {
+        if node.is_error() {
+            styles.push("filled");
+            color = Some("red");
+            extra.push("fillcolor=\"#ffcccc\"".to_string());
+        } else if node.is_missing() {
+            if !styles.contains(&"dashed") {
+                styles.push("dashed");
+            }
+            color = Some("red");
+        } else if node.has_error() {
+            color = Some("orange");
+        }
+    }
+    let mut attributes = String::new();
+    if !styles.is_empty() {
+        attributes.push_str(&format!(", style=\"{}\"", styles.join(",")));
+    }
+    if let Some(color) = color {
+        attributes.push_str(&format!(", color={}", color));
+    }
+    for attribute in extra {
+        attributes.push_str(&format!(", {}", attribute));
+    }
+    attributes
+}
+
+/// Whether the node is output, where `MISSING` nodes are kept with `named_only` since they are mostly anonymous
+/// punctuation like a missing `;`.
+pub fn is_output_node(node: Node, named_only: bool) -> bool {
+    !named_only || node.is_named() || node.is_missing()
+}
+
+/// Counts all nodes below `node` which would be rendered.
+fn count_descendants(node: Node, named_only: bool) -> usize {
+    let mut cursor = node.walk();
+    node.children(&mut cursor)
+        .filter(|child| is_output_node(*child, named_only))
+        .map(|child| 1 + count_descendants(child, named_only))
+        .sum()
+}
+
+/// Escapes a string for a double quoted dot attribute.
+fn escape_string(string: &str) -> String {
+    string
+        .replace("\\", "\\\\")
+        .replace("\"", "\\\"")
+        .replace("\n", "\\n")
+        .replace("\r", "\\r")
+        .replace("\t", "\\t")
+        .replace("\x08", "\\b") // backspace
+        .replace("\x0c", "\\f") // form feed
+}
+
+/// The node text for the label, truncated by characters so multi-byte characters like emojis are never split.
+fn node_text(node: Node, code: &str, options: &GraphvizOptions) -> String {
+    let text = &code[node.byte_range()];
+    let text = if options.collapse_whitespace {
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    } else {
+        text.to_string()
+    };
+    truncate(
+        &text,
+        options.max_text_length.unwrap_or(DEFAULT_MAX_TEXT_LENGTH),
+    )
+}
+
+/// Fills the label template of the options for the node, with the placeholder values escaped by `escape_value`
+/// and the rest of the template by `escape_literal`.
+///
+/// Unknown placeholders are kept as they are.
+fn node_label(
+    node: Node,
+    field: Option<&str>,
+    code: &str,
+    options: &GraphvizOptions,
+    escape_value: fn(&str) -> String,
+    escape_literal: fn(&str) -> String,
+) -> String {
+    let kind = if options.highlight_errors && node.is_missing() {
+        format!("MISSING {}", node.kind())
+    } else {
+        node.kind().to_string()
+    };
+    let start = node.start_position();
+    let end = node.end_position();
+    let template = options
+        .label_template
+        .as_deref()
+        .unwrap_or(DEFAULT_LABEL_TEMPLATE);
+    let mut label = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        label.push_str(&escape_literal(&rest[..open]));
+        let after_open = &rest[open + 1..];
+        let Some(close) = after_open.find('}') else {
+            rest = &rest[open..];
+            break;
+        };
+        let value = match &after_open[..close] {
+            "kind" => Some(kind.clone()),
+            "range" => Some(format!("{} {}", node.start_byte(), node.end_byte())),
+            "start" => Some(node.start_byte().to_string()),
+            "end" => Some(node.end_byte().to_string()),
+            "point" => Some(format!(
+                "{}:{}-{}:{}",
+                start.row, start.column, end.row, end.column
+            )),
+            "text" => Some(node_text(node, code, options)),
+            "field" => Some(field.unwrap_or_default().to_string()),
+            _ => None,
+        };
+        match value {
+            Some(value) => {
+                label.push_str(&escape_value(&value));
+                rest = &after_open[close + 1..];
+            }
+            None => {
+                label.push_str(&escape_literal("{"));
+                rest = after_open;
+            }
+        }
+    }
+    label.push_str(&escape_literal(rest));
+    label
+}
+
+/// The literal parts of a label template only need quotes escaped, since dot allows line breaks in quoted strings.
+fn escape_template(template: &str) -> String {
+    template.replace("\"", "\\\"")
+}
+
+/// The tree root, or the smallest node covering the focus range of the options.
+fn graph_root<'a>(tree: &'a Tree, options: &GraphvizOptions) -> Node<'a> {
+    let root_node = tree.root_node();
+    let Some(focus) = &options.focus else {
+        return root_node;
+    };
+    let focused = if options.named_only {
+        root_node.named_descendant_for_byte_range(focus.start, focus.end)
+    } else {
+        root_node.descendant_for_byte_range(focus.start, focus.end)
+    };
+    focused.unwrap_or(root_node)
+}
+
+/// Numbers all nodes of the tree from 1 in pre-order by their tree-sitter node ID, which is stable across runs
+/// unlike the node ID itself. The whole tree is numbered, so omitting nodes, limiting the depth or focusing a
+/// subtree leaves the IDs of the output nodes unchanged.
+pub fn stable_node_ids(root: Node) -> HashMap<usize, usize> {
+    let mut id_map = HashMap::new();
+    let mut cursor = root.walk();
+    loop {
+        id_map.insert(cursor.node().id(), id_map.len() + 1);
+        if cursor.goto_first_child() || cursor.goto_next_sibling() {
+            continue;
+        }
+        loop {
+            if !cursor.goto_parent() {
+                return id_map;
+            }
+            if cursor.goto_next_sibling() {
+                break;
+            }
+        }
     }
+}
 
+pub fn generate_dot_graph(tree: &Tree, code: &str, options: &GraphvizOptions) -> String {
     fn process_node(
         node: Node,
+        field: Option<&str>,
         graph_string: &mut String,
-        code: &String,
-        id_map: &mut HashMap<usize, usize>,
+        code: &str,
+        id_map: &HashMap<usize, usize>,
+        options: &GraphvizOptions,
+        depth: usize,
     ) {
-        let next_id = id_map.len() + 1;
-        let stable_id = *id_map.entry(node.id()).or_insert(next_id);
+        let stable_id = id_map[&node.id()];
         let node_id = format!("node_{}", stable_id);
 
-        let node_content = node
-            .utf8_text(code.as_ref())
-            .expect("Converting to UTF8 with the node range should succeed");
-        let max_node_content_length = 60;
-        let truncated_node_content = if node_content.len() > max_node_content_length {
-            format!("{}...", &node_content[..max_node_content_length])
-        } else {
-            node_content.to_string()
-        };
-
-        let escaped_node_content = escape_string(&truncated_node_content);
+        let mut label = node_label(node, field, code, options, escape_string, escape_template);
+        if let Some(capture_names) = options.captures.as_ref().and_then(|c| c.get(&node.id())) {
+            for capture_name in capture_names {
+                label.push_str(&format!("\n@{}", escape_string(capture_name)));
+            }
+        }
         graph_string.push_str(&format!(
-            "{}[label=\"{} {} {}\n{}\"];\n",
+            "{}[label=\"{}\"{}];\n",
             node_id,
-            node.kind().replace("\"", "\\\""),
-            node.byte_range().start,
-            node.byte_range().end,
-            escaped_node_content
+            label,
+            node_attributes(node, options)
         ));
 
-        for child in node.children(&mut node.walk()) {
-            let next_child_id = id_map.len() + 1;
-            let child_stable_id = *id_map.entry(child.id()).or_insert(next_child_id);
-            let child_id = format!("node_{}", child_stable_id);
-            graph_string.push_str(&format!("{} -> {}[label=\"\"];\n", node_id, child_id));
-            process_node(child, graph_string, code, id_map);
+        if options
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            let elided = count_descendants(node, options.named_only);
+            if elided > 0 {
+                // not prefixed with node_ since these do not have stable IDs
+                graph_string.push_str(&format!(
+                    "more_{}[label=\"… {} more\", shape=plaintext];\n{} -> more_{}[label=\"\", style=dotted];\n",
+                    stable_id, elided, node_id, stable_id
+                ));
+            }
+            return;
+        }
+
+        // walking with the cursor instead of node.children() to get the field names like name: or body:
+        let mut cursor = node.walk();
+        if !cursor.goto_first_child() {
+            return;
+        }
+        loop {
+            let child = cursor.node();
+            if !is_output_node(child, options.named_only) {
+                if !cursor.goto_next_sibling() {
+                    break;
+                }
+                continue;
+            }
+            let child_id = format!("node_{}", id_map[&child.id()]);
+            let field = cursor.field_name();
+            graph_string.push_str(&format!(
+                "{} -> {}[label=\"{}\"];\n",
+                node_id,
+                child_id,
+                escape_string(field.unwrap_or_default())
+            ));
+            process_node(child, field, graph_string, code, id_map, options, depth + 1);
+            if !cursor.goto_next_sibling() {
+                break;
+            }
         }
     }
 
     let mut graph_string = String::new();
-    let mut id_map = HashMap::new();
-    let root_node = tree.root_node();
-    process_node(root_node, &mut graph_string, code, &mut id_map);
-    format!("digraph name {{\n{}}}", graph_string)
+    let root_node = graph_root(tree, options);
+    let id_map = stable_node_ids(tree.root_node());
+    process_node(
+        root_node,
+        field_name(root_node),
+        &mut graph_string,
+        code,
+        &id_map,
+        options,
+        0,
+    );
+    if options.highlight_errors {
+        graph_string.push_str(ERROR_LEGEND);
+    }
+    if let Some(captures) = &options.captures {
+        graph_string.push_str(&capture_legend(captures));
+    }
+    let graph_attributes = match &options.rankdir {
+        Some(rankdir) => format!("rankdir={};\n", rankdir),
+        None => String::new(),
+    };
+    format!("digraph name {{\n{}{}}}", graph_attributes, graph_string)
+}
+
+/// The nodes and edges of the graph in the same order as `generate_dot_graph` outputs them, for the other
+/// diagram formats.
+struct Graph<'a> {
+    /// The stable ID, the node and its field name.
+    nodes: Vec<(usize, Node<'a>, Option<&'static str>)>,
+    /// The parent ID, the child ID and the field name of the child.
+    edges: Vec<(usize, usize, Option<&'static str>)>,
+    /// The ID of the nodes at `max_depth` with the count of their collapsed descendants.
+    collapsed: Vec<(usize, usize)>,
+}
+
+fn collect_graph<'a>(tree: &'a Tree, options: &GraphvizOptions) -> Graph<'a> {
+    fn collect_node<'a>(
+        node: Node<'a>,
+        field: Option<&'static str>,
+        id_map: &HashMap<usize, usize>,
+        options: &GraphvizOptions,
+        depth: usize,
+        graph: &mut Graph<'a>,
+    ) {
+        let id = id_map[&node.id()];
+        graph.nodes.push((id, node, field));
+        if options
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            let collapsed = count_descendants(node, options.named_only);
+            if collapsed > 0 {
+                graph.collapsed.push((id, collapsed));
+            }
+            return;
+        }
+        let mut cursor = node.walk();
+        if !cursor.goto_first_child() {
+            return;
+        }
+        loop {
+            let child = cursor.node();
+            if is_output_node(child, options.named_only) {
+                graph
+                    .edges
+                    .push((id, id_map[&child.id()], cursor.field_name()));
+                collect_node(
+                    child,
+                    cursor.field_name(),
+                    id_map,
+                    options,
+                    depth + 1,
+                    graph,
+                );
+            }
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+
+    let root_node = graph_root(tree, options);
+    let id_map = stable_node_ids(tree.root_node());
+    let mut graph = Graph {
+        nodes: Vec::new(),
+        edges: Vec::new(),
+        collapsed: Vec::new(),
+    };
+    collect_node(
+        root_node,
+        field_name(root_node),
+        &id_map,
+        options,
+        0,
+        &mut graph,
+    );
+    graph
+}
+
+/// Escapes text for a double quoted Mermaid label with its entity codes, line breaks become `<br/>`.
+fn escape_mermaid(string: &str) -> String {
+    string
+        .replace('#', "#35;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+        .replace("\r\n", "<br/>")
+        .replace(['\n', '\r'], "<br/>")
+}
+
+/// The same graph as `generate_dot_graph` as Mermaid flowchart, with the same node IDs and labels.
+///
+/// The anonymous node style is supported, while error highlighting and captures are only in the dot graph.
+pub fn generate_mermaid_graph(tree: &Tree, code: &str, options: &GraphvizOptions) -> String {
+    let graph = collect_graph(tree, options);
+    let direction = options.rankdir.as_deref().unwrap_or("TB");
+    let mut output = format!("flowchart {}\n", direction);
+    for (id, node, field) in &graph.nodes {
+        let label = node_label(*node, *field, code, options, escape_mermaid, escape_mermaid);
+        let class = if options.style_anonymous && !node.is_named() {
+            ":::anonymous"
+        } else {
+            ""
+        };
+        output.push_str(&format!("    node_{}[\"{}\"]{}\n", id, label, class));
+    }
+    for (parent_id, child_id, field) in &graph.edges {
+        match field {
+            Some(field) => output.push_str(&format!(
+                "    node_{} -->|\"{}\"| node_{}\n",
+                parent_id,
+                escape_mermaid(field),
+                child_id
+            )),
+            None => output.push_str(&format!("    node_{} --> node_{}\n", parent_id, child_id)),
+        }
+    }
+    for (id, collapsed) in &graph.collapsed {
+        output.push_str(&format!(
+            "    more_{}[\"… {} more\"]\n    node_{} -.-> more_{}\n",
+            id, collapsed, id, id
+        ));
+    }
+    if options.style_anonymous {
+        output.push_str("    classDef anonymous stroke-dasharray: 5 5, color: gray\n");
+    }
+    output
+}
+
+/// Escapes text for a double quoted PlantUML string, where `\n` is a line break and quotes are written as
+/// Unicode code point.
+fn escape_plantuml(string: &str) -> String {
+    string
+        .replace('\\', "\\\\")
+        .replace('"', "<U+0022>")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n")
+}
+
+/// The same graph as `generate_dot_graph` as PlantUML diagram, with the same node IDs and labels.
+///
+/// The anonymous node style is supported, while error highlighting and captures are only in the dot graph.
+/// PlantUML only knows top to bottom and left to right, so `BT` and `RL` warn and fall back to `TB` and `LR`.
+pub fn generate_plantuml_graph(tree: &Tree, code: &str, options: &GraphvizOptions) -> String {
+    let graph = collect_graph(tree, options);
+    let mut output = String::from("@startuml\n");
+    let rankdir = options.rankdir.as_deref().unwrap_or("TB");
+    if rankdir == "BT" || rankdir == "RL" {
+        eprintln!(
+            "Warning: PlantUML cannot reverse the direction, --graphviz-rankdir {} is drawn as {}",
+            rankdir,
+            if rankdir == "BT" { "TB" } else { "LR" }
+        );
+    }
+    if rankdir == "LR" || rankdir == "RL" {
+        output.push_str("left to right direction\n");
+    }
+    for (id, node, field) in &graph.nodes {
+        let label = node_label(
+            *node,
+            *field,
+            code,
+            options,
+            escape_plantuml,
+            escape_plantuml,
+        );
+        let style = if options.style_anonymous && !node.is_named() {
+            " #line.dashed;line:gray;text:gray"
+        } else {
+            ""
+        };
+        output.push_str(&format!(
+            "rectangle \"{}\" as node_{}{}\n",
+            label, id, style
+        ));
+    }
+    for (parent_id, child_id, field) in &graph.edges {
+        match field {
+            Some(field) => output.push_str(&format!(
+                "node_{} --> node_{} : {}\n",
+                parent_id,
+                child_id,
+                escape_plantuml(field)
+            )),
+            None => output.push_str(&format!("node_{} --> node_{}\n", parent_id, child_id)),
+        }
+    }
+    for (id, collapsed) in &graph.collapsed {
+        output.push_str(&format!(
+            "label \"… {} more\" as more_{}\nnode_{} ..> more_{}\n",
+            collapsed, id, id, id
+        ));
+    }
+    output.push_str("@enduml\n");
+    output
+}
+
+fn run_dot(
+    program: &str,
+    dot_graph: &str,
+    format: &str,
+    output_path: &str,
+    dot_args: &[String],
+) -> Result<(), String> {
+    let mut dot_process = Command::new(program)
+        .arg(format!("-T{}", format))
+        .arg("-o")
+        .arg(output_path)
+        .args(dot_args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => format!(
+                "The Graphviz '{}' executable was not found on the PATH, install Graphviz (e.g. brew install graphviz) to use --render",
+                program
+            ),
+            _ => format!("Failed to start '{}': {}", program, e),
+        })?;
+    if let Some(mut stdin) = dot_process.stdin.take() {
+        stdin
+            .write_all(dot_graph.as_bytes())
+            .map_err(|e| format!("Failed to write the dot graph to '{}': {}", program, e))?;
+    }
+    let output = dot_process
+        .wait_with_output()
+        .map_err(|e| format!("Failed to wait for '{}': {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "'{}' failed with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Renders the dot graph into `output_path` with the local `dot` executable of Graphviz.
+///
+/// `dot_args` are passed through as they are, like `-Gsplines=ortho` or `-Kneato` for another layout engine.
+pub fn render_dot_graph(
+    dot_graph: &str,
+    format: &str,
+    output_path: &str,
+    dot_args: &[String],
+) -> Result<(), String> {
+    run_dot("dot", dot_graph, format, output_path, dot_args)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::languages::tests::run_with_args;
     use crate::{get_command, handle_args};
     use std::io::{Read, Write};
 
@@ -89,6 +646,254 @@ mod tests {
         assert_eq!(output.lines().count(), 28);
     }
 
+    #[test]
+    fn test_dot_graph_field_names_and_anonymous_style() {
+        let output = run_graphviz("test = 1", "python", vec!["--graphviz-anonymous-style"]);
+        assert!(output.contains("node_3 -> node_4[label=\"left\"];\n"));
+        assert!(output.contains("node_3 -> node_5[label=\"\"];\n"));
+        assert!(output.contains("node_3 -> node_6[label=\"right\"];\n"));
+        assert!(output.contains("node_4[label=\"identifier 0 4\ntest\"];\n"));
+        assert!(output.contains(
+            "node_5[label=\"= 5 6\n=\", style=\"dashed\", color=gray50, fontcolor=gray50];\n"
+        ));
+    }
+
+    fn run_graphviz(code: &str, language: &str, extra_args: Vec<&str>) -> String {
+        run_with_args(
+            code,
+            language,
+            [vec!["--graphviz-only"], extra_args].concat(),
+        )
+    }
+
+    #[test]
+    fn test_dot_graph_named_only() {
+        let output = run_graphviz("test = \"1\"", "python", vec!["--graphviz-named-only"]);
+        assert!(!output.contains("label=\"= "));
+        // the IDs of the full tree are kept, so node_5 is the omitted =
+        assert!(output.contains("node_3 -> node_4[label=\"left\"];\n"));
+        assert!(output.contains("node_3 -> node_6[label=\"right\"];\n"));
+        // string, string_start, string_content and string_end are kept since they are named
+        assert_eq!(output.lines().filter(|line| line.contains("->")).count(), 7);
+    }
+
+    #[test]
+    fn test_dot_graph_max_depth() {
+        let output = run_graphviz("test = 1", "python", vec!["--graphviz-max-depth", "1"]);
+        assert_eq!(
+            output,
+            "digraph name {\nnode_1[label=\"module 0 8\ntest = 1\"];\nnode_1 -> node_2[label=\"\"];\nnode_2[label=\"expression_statement 0 8\ntest = 1\"];\nmore_2[label=\"… 4 more\", shape=plaintext];\nnode_2 -> more_2[label=\"\", style=dotted];\n}"
+        );
+    }
+
+    #[test]
+    fn test_dot_graph_focus() {
+        let output = run_graphviz(
+            "x = 1\ny = foo(2)",
+            "python",
+            vec!["--graphviz-focus", "10:16", "--graphviz-named-only"],
+        );
+        assert!(output.starts_with("digraph name {\nnode_11[label=\"call 10 16\nfoo(2)\"];\n"));
+        assert!(output.contains("node_11 -> node_12[label=\"function\"];\n"));
+        assert!(output.contains("node_11 -> node_13[label=\"arguments\"];\n"));
+        assert!(!output.contains("label=\"x"));
+    }
+
+    #[test]
+    fn test_dot_graph_ids_independent_of_reduction() {
+        let code = "x = 1\ny = foo(2)";
+        let full = run_graphviz(code, "python", vec![]);
+        for args in [
+            vec!["--graphviz-named-only"],
+            vec!["--graphviz-max-depth", "2"],
+            vec!["--graphviz-focus", "10:16"],
+        ] {
+            let output = run_graphviz(code, "python", args);
+            // every node and edge has the same ID as in the full graph
+            for line in output
+                .lines()
+                .filter(|line| line.starts_with("node_") && !line.contains("more_"))
+            {
+                assert!(full.contains(line), "{} is not in the full graph", line);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dot_graph_highlight_errors() {
+        let output = run_graphviz(
+            "fn main() {\n    let x = 1\n}",
+            "rust",
+            vec!["--graphviz-errors"],
+        );
+        assert!(output.contains("[label=\"MISSING ; 25 25\n\", style=\"dashed\", color=red];\n"));
+        // the missing ; is anonymous, but kept without anonymous nodes
+        let named_only = run_graphviz(
+            "fn main() {\n    let x = 1\n}",
+            "rust",
+            vec!["--graphviz-errors", "--graphviz-named-only"],
+        );
+        assert!(
+            named_only.contains("[label=\"MISSING ; 25 25\n\", style=\"dashed\", color=red];\n")
+        );
+        assert!(!named_only.contains("label=\"{ "));
+        assert!(output.contains("node_1[label=\"source_file 0 27\n"));
+        assert!(output.contains("\", color=orange];\n"));
+        assert!(output.contains("subgraph cluster_legend {\n"));
+        assert!(output.ends_with("}\n}"));
+
+        let output = run_graphviz("x = 1\n)\n", "python", vec!["--graphviz-errors"]);
+        assert!(output.contains(
+            "[label=\"ERROR 6 7\n)\", style=\"filled\", color=red, fillcolor=\"#ffcccc\"];\n"
+        ));
+
+        // no legend and styling without the option
+        let output = run_graphviz("x = 1\n)\n", "python", vec![]);
+        assert!(!output.contains("cluster_legend"));
+        assert!(!output.contains("color=red"));
+    }
+
+    #[test]
+    fn test_dot_graph_captures() {
+        let output = run_graphviz(
+            "test = 1",
+            "python",
+            vec![
+                "--graphviz-captures",
+                "--highlights",
+                "(identifier) @variable (integer) @number (integer) @constant (assignment left: (_) @_left (#eq? @_left \"test\"))",
+            ],
+        );
+        assert!(output.contains(
+            "node_4[label=\"identifier 0 4\ntest\n@variable\", style=\"filled\", fillcolor=\""
+        ));
+        assert!(output.contains(
+            "node_6[label=\"integer 7 8\n1\n@number\n@constant\", style=\"filled\", fillcolor=\""
+        ));
+        // uncaptured nodes are greyed out
+        assert!(output
+            .contains("node_1[label=\"module 0 8\ntest = 1\", color=gray70, fontcolor=gray60];\n"));
+        assert!(output.contains(
+            "subgraph cluster_captures {\nlabel=\"Captures\";\ncapture_0[label=\"@constant\""
+        ));
+        assert!(output.contains("capture_2[label=\"@variable\""));
+        // the helper capture @_left is only for the predicate
+        assert!(!output.contains("@_left"));
+    }
+
+    #[test]
+    fn test_dot_graph_rankdir() {
+        let output = run_graphviz("test = 1", "python", vec!["--graphviz-rankdir", "LR"]);
+        assert!(output.starts_with("digraph name {\nrankdir=LR;\nnode_1[label=\"module 0 8"));
+    }
+
+    #[test]
+    fn test_dot_graph_label_truncates_by_characters() {
+        // byte 60 would be inside an emoji
+        let code = format!("x = \"{}\"", "😄".repeat(20));
+        let output = run_graphviz(&code, "python", vec!["--graphviz-max-text", "6"]);
+        assert!(output.contains("[label=\"string 4 86\n\\\"😄😄😄😄😄...\"];\n"));
+    }
+
+    #[test]
+    fn test_dot_graph_label_template() {
+        let output = run_graphviz(
+            "if x:\n    y = 1",
+            "python",
+            vec![
+                "--graphviz-label",
+                "{field}: {kind} {point}\\n{text}",
+                "--graphviz-collapse-whitespace",
+            ],
+        );
+        assert!(output.contains("node_2[label=\": if_statement 0:0-1:9\nif x: y = 1\"];\n"));
+        assert!(output.contains("[label=\"condition: identifier 0:3-0:4\nx\"];\n"));
+    }
+
+    #[test]
+    fn test_mermaid_graph() {
+        let output = run_graphviz(
+            "x = \"<a>\"",
+            "python",
+            vec!["--tree", "mermaid", "--graphviz-named-only"],
+        );
+        assert!(output.starts_with(
+            "flowchart TB\n    node_1[\"module 0 9<br/>x = #quot;#lt;a#gt;#quot;\"]\n"
+        ));
+        assert!(output.contains("    node_3 -->|\"left\"| node_4\n"));
+        assert!(output.contains("    node_1 --> node_2\n"));
+    }
+
+    #[test]
+    fn test_plantuml_graph() {
+        let output = run_graphviz(
+            "x = 1",
+            "python",
+            vec![
+                "--tree",
+                "plantuml",
+                "--graphviz-max-depth",
+                "2",
+                "--graphviz-label",
+                "{kind}",
+            ],
+        );
+        assert_eq!(
+            output,
+            "@startuml\nrectangle \"module\" as node_1\nrectangle \"expression_statement\" as node_2\n\
+             rectangle \"assignment\" as node_3\nnode_1 --> node_2\nnode_2 --> node_3\n\
+             label \"… 3 more\" as more_3\nnode_3 ..> more_3\n@enduml\n"
+        );
+    }
+
+    #[test]
+    fn test_plantuml_graph_rankdir() {
+        for (rankdir, direction) in [
+            ("TB", ""),
+            ("BT", ""),
+            ("LR", "left to right direction\n"),
+            ("RL", "left to right direction\n"),
+        ] {
+            let output = run_graphviz(
+                "x",
+                "python",
+                vec!["--tree", "plantuml", "--graphviz-rankdir", rankdir],
+            );
+            assert!(output.starts_with(&format!("@startuml\n{}rectangle ", direction)));
+        }
+    }
+
+    #[test]
+    fn test_render_without_dot_executable() {
+        let error = super::run_dot(
+            "dot-executable-which-does-not-exist",
+            "digraph name {}",
+            "svg",
+            "graph.svg",
+            &[],
+        )
+        .unwrap_err();
+        assert!(error.contains("was not found on the PATH"));
+    }
+
+    /// Render via the dot process which should be on the PATH.
+    #[test]
+    fn test_render_svg() {
+        let output_path = std::env::temp_dir().join("tree-sitter-cli-via-rust-test.svg");
+        let output_path = output_path.to_str().unwrap();
+        let dot_graph = run_graphviz("test = 1", "python", vec![]);
+        super::render_dot_graph(
+            &dot_graph,
+            "svg",
+            output_path,
+            &["-Gsplines=ortho".to_string()],
+        )
+        .expect("dot application should be available on the PATH");
+        let svg = std::fs::read_to_string(output_path).expect("SVG file should be written");
+        assert!(svg.contains("<svg"));
+        std::fs::remove_file(output_path).expect("SVG file should be removable");
+    }
+
     /// Validate if the generated graph code has valid syntax via the dot process which should be on the PATH.
     #[test]
     fn test_dot_graph_creation_via_dot_process() {
@@ -147,6 +952,7 @@ mod tests {
     /// 1. The graphviz output is deterministic (same input produces identical output)
     /// 2. Node IDs in the generated graph are sequential starting from 1
     #[test]
+    #[allow(clippy::explicit_counter_loop)]
     fn test_dot_graph_stable_ids() {
         let code = r#"let x = 1;"#;
         let mut output1 = Vec::new();
@@ -197,4 +1003,41 @@ mod tests {
             expected_id += 1;
         }
     }
+
+    #[test]
+    fn test_stable_node_ids_preorder() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_rust::LANGUAGE.into())
+            .expect("Rust grammar should load");
+        let tree = parser.parse("let x = 1;", None).unwrap();
+        let root = tree.root_node();
+        let let_declaration = root.child(0).unwrap();
+        let ids = super::stable_node_ids(root);
+        let mut kinds: Vec<(usize, &str)> = [root, let_declaration]
+            .into_iter()
+            .chain(let_declaration.children(&mut let_declaration.walk()))
+            .map(|node| (ids[&node.id()], node.kind()))
+            .collect();
+        kinds.sort();
+        assert_eq!(
+            kinds,
+            vec![
+                (1, "source_file"),
+                (2, "let_declaration"),
+                (3, "let"),
+                (4, "identifier"),
+                (5, "="),
+                (6, "integer_literal"),
+                (7, ";")
+            ]
+        );
+        // the JSON tree omits the anonymous nodes by default, but keeps the IDs of the dot graph
+        let json: serde_json::Value =
+            serde_json::from_str(&run_graphviz("let x = 1;", "rust", vec!["--tree", "json"]))
+                .unwrap();
+        let children = &json["children"][0]["children"];
+        assert_eq!(children[0]["id"], 4);
+        assert_eq!(children[1]["id"], 6);
+    }
 }
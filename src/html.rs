@@ -0,0 +1,173 @@
+use crate::languages::Span;
+use crate::text::escape_html;
+use crate::theme::Theme;
+use std::io::Write;
+
+#[derive(Default)]
+pub struct HtmlOptions {
+    /// Prefix every line with `<span class="hl-line-number">`.
+    pub line_numbers: bool,
+    /// Add the theme style of each capture as `style` attribute, for places without the stylesheet.
+    pub inline_styles: bool,
+    /// Wrap the output in a page with a stylesheet generated from the theme.
+    pub standalone: bool,
+}
+
+/// The classes of the capture name and its parents, like `hl-keyword hl-keyword-function`, so a stylesheet can
+/// style `keyword.function` with the rule of `keyword`.
+fn class_names(capture_name: &str) -> String {
+    let parts: Vec<&str> = capture_name.split('.').collect();
+    (1..=parts.len())
+        .map(|length| format!("hl-{}", escape_html(&parts[..length].join("-"))))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn span_start(capture_name: &str, theme: &Theme, options: &HtmlOptions) -> String {
+    let css = theme
+        .style(capture_name)
+        .map(|style| style.css())
+        .unwrap_or_default();
+    if options.inline_styles && !css.is_empty() {
+        format!(
+            "<span class=\"{}\" style=\"{}\">",
+            class_names(capture_name),
+            css
+        )
+    } else {
+        format!("<span class=\"{}\">", class_names(capture_name))
+    }
+}
+
+fn stylesheet(theme: &Theme) -> String {
+    let mut css = String::from(".hl-line-number { color: #888; user-select: none; }\n");
+    for (capture_name, style) in theme.styles() {
+        let declarations = style.css();
+        if !declarations.is_empty() {
+            css.push_str(&format!(
+                ".hl-{} {{ {}; }}\n",
+                capture_name.replace('.', "-"),
+                declarations
+            ));
+        }
+    }
+    css
+}
+
+/// Outputs the code in `<pre class="hl">` with a `<span class="hl-…">` for every span. Spans are closed at line
+/// breaks and reopened on the next line, so every line is valid HTML on its own.
+pub fn process_html<W>(
+    spans: &[Span],
+    code: &str,
+    theme: &Theme,
+    options: &HtmlOptions,
+    writer: &mut W,
+) where
+    W: Write,
+{
+    let mut lines = vec![String::new()];
+    let mut push_text = |text: &str, capture_name: Option<&str>| {
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                lines.push(String::new());
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let current = lines.last_mut().unwrap();
+            match capture_name {
+                Some(capture_name) => current.push_str(&format!(
+                    "{}{}</span>",
+                    span_start(capture_name, theme, options),
+                    escape_html(line)
+                )),
+                None => current.push_str(&escape_html(line)),
+            }
+        }
+    };
+    let mut position = 0;
+    for span in spans {
+        push_text(&code[position..span.range.start], None);
+        push_text(&code[span.range.clone()], Some(span.name));
+        position = span.range.end;
+    }
+    push_text(&code[position..], None);
+
+    if options.line_numbers {
+        let width = lines.len().to_string().len();
+        for (index, line) in lines.iter_mut().enumerate() {
+            *line = format!(
+                "<span class=\"hl-line-number\">{:>width$}</span> {}",
+                index + 1,
+                line,
+                width = width
+            );
+        }
+    }
+    let pre = format!("<pre class=\"hl\">{}</pre>\n", lines.join("\n"));
+    let output = if options.standalone {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            stylesheet(theme),
+            pre
+        )
+    } else {
+        pre
+    };
+    write!(writer, "{}", output).expect("write should succeed");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args;
+
+    fn run_html(code: &str, highlights: &str, extra_args: Vec<&str>) -> String {
+        let args = [
+            vec!["--highlights", highlights, "--format", "html"],
+            extra_args,
+        ]
+        .concat();
+        run_with_args(code, "rust", args)
+    }
+
+    #[test]
+    fn test_html_classes_and_escaping() {
+        let output = run_html(
+            "fn f() -> A<'a> {}",
+            r#""fn" @keyword.function (lifetime) @label"#,
+            vec![],
+        );
+        assert_eq!(
+            output,
+            "<pre class=\"hl\"><span class=\"hl-keyword hl-keyword-function\">fn</span> f() -&gt; A&lt;<span class=\"hl-label\">'a</span>&gt; {}</pre>\n"
+        );
+    }
+
+    #[test]
+    fn test_html_line_numbers_inline_styles_and_standalone() {
+        let output = run_html(
+            "/* a\nb */\nfn",
+            "(block_comment) @comment",
+            vec!["--html-line-numbers", "--html-inline-styles"],
+        );
+        let comment_start =
+            "<span class=\"hl-comment\" style=\"color: #7f848e; font-style: italic\">";
+        assert_eq!(
+            output,
+            format!(
+                "<pre class=\"hl\"><span class=\"hl-line-number\">1</span> {}/* a</span>\n\
+                 <span class=\"hl-line-number\">2</span> {}b */</span>\n\
+                 <span class=\"hl-line-number\">3</span> fn</pre>\n",
+                comment_start, comment_start
+            )
+        );
+
+        let output = run_html("fn", "\"fn\" @keyword", vec!["--html-standalone"]);
+        assert!(output.starts_with("<!DOCTYPE html>\n"));
+        assert!(output.contains(".hl-keyword { color: #c678dd; }\n"));
+        assert!(output.contains(".hl-comment { color: #7f848e; font-style: italic; }\n"));
+        assert!(output.ends_with(
+            "<body>\n<pre class=\"hl\"><span class=\"hl-keyword\">fn</span></pre>\n</body>\n</html>\n"
+        ));
+    }
+}
@@ -0,0 +1,17 @@
+use serde_json::{json, Value};
+use tree_sitter::{Point, Range};
+
+pub fn point_to_json(point: Point) -> Value {
+    json!({ "row": point.row, "column": point.column })
+}
+
+/// Byte offsets are kept as the primary positions for compatibility with Kotlin Emacs, the 0-based
+/// row/column points are added for LSP-style consumers.
+pub fn range_to_json(range: Range) -> Value {
+    json!({
+        "start": range.start_byte,
+        "end": range.end_byte,
+        "start_point": point_to_json(range.start_point),
+        "end_point": point_to_json(range.end_point),
+    })
+}
@@ -1,6 +1,13 @@
+use crate::config::CaptureMap;
+use crate::navigation::field_name;
+use crate::predicates::{capture_priority, Predicates};
+use crate::text::{glob_matches, truncate};
+use serde_json::Value;
+use std::cmp::Reverse;
 use std::io::Write;
+use std::ops::Range;
 use std::process::exit;
-use tree_sitter::{Parser, Query, StreamingIterator, Tree};
+use tree_sitter::{Node, Parser, Query, StreamingIterator, Tree};
 use tree_sitter_md::{INLINE_LANGUAGE, LANGUAGE};
 
 pub static LANGUAGES: [&str; 16] = [
@@ -85,63 +92,294 @@ pub fn set_parser_language(language: &&String, parser: &mut Parser, language_enu
     .unwrap_or_else(|_| panic!("Error loading {} grammar", language))
 }
 
-pub fn process_query<W>(
-    parser: Parser,
-    highlights: &str,
-    tree: &Tree,
-    code: &String,
-    writer: &mut W,
-) where
-    W: Write,
-{
+/// A capture of the highlights query like `keyword` for the node.
+pub struct Capture<'a> {
+    pub name: &'a str,
+    pub node: Node<'a>,
+    /// The byte range of the node, unless it was changed by `#offset!`.
+    pub range: Range<usize>,
+    /// The `#set! priority`, where higher priorities win over overlapping captures.
+    pub priority: usize,
+}
+
+pub fn create_query(parser: &Parser, highlights: &str) -> Query {
     let parser_language = parser.language().unwrap();
-    let query = match Query::new(&parser_language, highlights) {
+    match Query::new(&parser_language, highlights) {
         Ok(query) => query,
         Err(_) => {
             eprintln!("Failed to create query for passed highlights");
             exit(1);
         }
-    };
+    }
+}
+
+/// The `--include-captures` and `--exclude-captures` glob patterns like `keyword.*`, matched against the capture
+/// names after the `--config` mapping.
+#[derive(Default)]
+pub struct CaptureFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl CaptureFilter {
+    /// Without include patterns, all captures are included.
+    pub fn matches(&self, capture_name: &str) -> bool {
+        let matches_any = |patterns: &[String]| {
+            patterns
+                .iter()
+                .any(|pattern| glob_matches(pattern, capture_name))
+        };
+        (self.include.is_empty() || matches_any(&self.include)) && !matches_any(&self.exclude)
+    }
+}
+
+/// The capture names of the query by capture index after applying the `--config` mapping for the language and
+/// the filter, where `None` marks captures which are dropped.
+pub fn map_capture_names(
+    query: &Query,
+    capture_map: &CaptureMap,
+    capture_filter: &CaptureFilter,
+    language: &str,
+) -> Vec<Option<String>> {
+    query
+        .capture_names()
+        .iter()
+        .map(|capture_name| {
+            capture_map
+                .map(language, capture_name)
+                .filter(|capture_name| capture_filter.matches(capture_name))
+        })
+        .collect()
+}
+
+/// The captures of one query match with the index of the pattern in the query.
+pub struct Match<'a> {
+    pub pattern_index: usize,
+    pub captures: Vec<Capture<'a>>,
+}
+
+/// Returns the query matches in order, with the captures named by `capture_names` from `map_capture_names()`.
+/// Matches which fail a general predicate like `#lua-match?` or where all captures were dropped are skipped.
+pub fn collect_matches<'a>(
+    query: &Query,
+    capture_names: &'a [Option<String>],
+    tree: &'a Tree,
+    code: &str,
+) -> Vec<Match<'a>> {
+    let predicates = Predicates::new(query, code);
+    let mut result = Vec::new();
     let mut query_cursor = tree_sitter::QueryCursor::new();
-    let mut matches = query_cursor.matches(&query, tree.root_node(), code.as_bytes());
+    let mut matches = query_cursor.matches(query, tree.root_node(), code.as_bytes());
     while let Some(m) = matches.next() {
-        for capture in m.captures {
-            let node = capture.node;
-            let capture_name = query.capture_names()[capture.index as usize];
-            writeln!(
-                writer,
-                "{} {} {}",
-                capture_name,
-                node.byte_range().start,
-                node.byte_range().end
-            )
-            .expect("write should succeed");
+        if !predicates.satisfied(m, code) {
+            continue;
+        }
+        let captures: Vec<Capture> = m
+            .captures
+            .iter()
+            .filter_map(|capture| {
+                capture_names[capture.index as usize]
+                    .as_ref()
+                    .map(|name| Capture {
+                        name,
+                        node: capture.node,
+                        range: predicates.capture_range(
+                            m.pattern_index,
+                            capture.index,
+                            capture.node,
+                            code,
+                        ),
+                        priority: capture_priority(query, m.pattern_index, capture.index),
+                    })
+            })
+            .collect();
+        if !captures.is_empty() {
+            result.push(Match {
+                pattern_index: m.pattern_index,
+                captures,
+            });
+        }
+    }
+    result
+}
+
+/// Returns the captures in the order of the query matches, named by `capture_names` from `map_capture_names()`.
+pub fn collect_captures<'a>(
+    query: &Query,
+    capture_names: &'a [Option<String>],
+    tree: &'a Tree,
+    code: &str,
+) -> Vec<Capture<'a>> {
+    collect_matches(query, capture_names, tree, code)
+        .into_iter()
+        .flat_map(|m| m.captures)
+        .collect()
+}
+
+/// A non-overlapping highlighted range of the source.
+pub struct Span<'a> {
+    pub name: &'a str,
+    pub range: Range<usize>,
+}
+
+/// Resolves the overlapping captures into non-overlapping spans in document order, for output formats which can
+/// only apply one capture to each character.
+///
+/// Each byte gets the innermost capture covering it, so `@string.escape` inside `@string` wins, unless an
+/// overlapping capture has a higher `#set! priority`. For captures with the same range, the first one of the
+/// query wins like in tree-sitter-highlight. Captures starting with `_` are helpers for predicates and are
+/// skipped.
+pub fn resolve_spans<'a>(captures: &[Capture<'a>]) -> Vec<Span<'a>> {
+    let captures: Vec<&Capture> = captures
+        .iter()
+        .filter(|capture| !capture.name.starts_with('_') && !capture.range.is_empty())
+        .collect();
+    let mut boundaries: Vec<usize> = captures
+        .iter()
+        .flat_map(|capture| [capture.range.start, capture.range.end])
+        .collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut by_start: Vec<usize> = (0..captures.len()).collect();
+    by_start.sort_by_key(|&index| captures[index].range.start);
+    let mut next_capture = 0;
+    let mut active: Vec<usize> = Vec::new();
+    let mut spans: Vec<Span> = Vec::new();
+    let mut previous_winner = None;
+    for segment in boundaries.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        while next_capture < by_start.len() && captures[by_start[next_capture]].range.start <= start
+        {
+            active.push(by_start[next_capture]);
+            next_capture += 1;
+        }
+        active.retain(|&index| captures[index].range.end > start);
+        let winner = active
+            .iter()
+            .min_by_key(|&&index| {
+                let capture = captures[index];
+                (Reverse(capture.priority), capture.range.len(), index)
+            })
+            .copied();
+        match winner {
+            Some(index) if previous_winner == Some(index) => {
+                spans.last_mut().unwrap().range.end = end;
+            }
+            Some(index) => spans.push(Span {
+                name: captures[index].name,
+                range: start..end,
+            }),
+            None => {}
+        }
+        previous_winner = winner;
+    }
+    spans
+}
+
+/// How `process_query()` outputs the captures.
+#[derive(Default)]
+pub struct QueryOutputOptions {
+    /// Group the captures of each query match after a `match {patternIndex}` line, indented by two spaces.
+    pub group_by_match: bool,
+    /// Append the node kind like `kind:identifier`, anonymous kinds are quoted like `kind:"fn"`.
+    pub kind: bool,
+    /// Append the field name of the node in its parent like `field:name`, if it has one.
+    pub field: bool,
+    /// Append the source text of the node truncated to this many characters as JSON string like `text:"f"`.
+    pub max_text_length: Option<usize>,
+}
+
+fn write_capture<W>(
+    capture: &Capture,
+    indent: &str,
+    options: &QueryOutputOptions,
+    code: &str,
+    writer: &mut W,
+) where
+    W: Write,
+{
+    let node = capture.node;
+    let mut line = format!(
+        "{}{} {} {}",
+        indent, capture.name, capture.range.start, capture.range.end
+    );
+    if options.kind {
+        if node.is_named() {
+            line.push_str(&format!(" kind:{}", node.kind()));
+        } else {
+            line.push_str(&format!(" kind:{}", Value::from(node.kind())));
+        }
+    }
+    if options.field {
+        if let Some(field) = field_name(node) {
+            line.push_str(&format!(" field:{}", field));
+        }
+    }
+    if let Some(max_text_length) = options.max_text_length {
+        let text = truncate(&code[capture.range.clone()], max_text_length);
+        line.push_str(&format!(" text:{}", Value::from(text)));
+    }
+    writeln!(writer, "{}", line).expect("write should succeed");
+}
+
+/// Outputs one capture per line as `{captureName} {byteRangeStart} {byteRangeEnd}`, followed by the node
+/// details enabled in `options`.
+pub fn process_query<W>(
+    query: &Query,
+    capture_names: &[Option<String>],
+    options: &QueryOutputOptions,
+    tree: &Tree,
+    code: &str,
+    writer: &mut W,
+) where
+    W: Write,
+{
+    if !options.group_by_match {
+        for capture in collect_captures(query, capture_names, tree, code) {
+            write_capture(&capture, "", options, code, writer);
+        }
+        return;
+    }
+    for m in collect_matches(query, capture_names, tree, code) {
+        writeln!(writer, "match {}", m.pattern_index).expect("write should succeed");
+        for capture in &m.captures {
+            write_capture(capture, "  ", options, code, writer);
         }
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use crate::{get_command, handle_args};
 
+    /// Runs the CLI with `--code` and `--language` followed by the extra arguments and returns the raw output,
+    /// which is only UTF-8 for the text formats.
+    pub fn run_with_args_raw(code: &str, language: &str, extra_args: Vec<&str>) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut args = vec!["main", "--code", code, "--language", language];
+        args.extend(extra_args);
+        handle_args(get_command().get_matches_from(args), &mut output);
+        output
+    }
+
+    /// Like `run_with_args_raw()`, but returns the output as string.
+    pub fn run_with_args(code: &str, language: &str, extra_args: Vec<&str>) -> String {
+        String::from_utf8(run_with_args_raw(code, language, extra_args))
+            .expect("Output array should be UTF-8")
+    }
+
     pub fn run_test_with_highlights<S: AsRef<str>>(
         code: S,
         language: S,
         highlights_query: &str,
         expected_output: &str,
     ) {
-        let mut output = Vec::new();
-        let args = get_command().get_matches_from(vec![
-            "main",
-            "--code",
+        let output = run_with_args(
             code.as_ref(),
-            "--language",
             language.as_ref(),
-            "--highlights",
-            highlights_query,
-        ]);
-        handle_args(args, &mut output);
-        let output = String::from_utf8(output).expect("Output array should be UTF-8");
+            vec!["--highlights", highlights_query],
+        );
         assert_eq!(expected_output, output);
     }
 
@@ -397,4 +635,89 @@ punctuation.delimiter 23 24
 "#,
         )
     }
+
+    const CAPTURES_HIGHLIGHTS: &str = r#"(function_item name: (identifier) @function.name body: (block) @scope)
+"fn" @keyword.function
+"let" @keyword
+(string_literal) @string
+(line_comment) @comment"#;
+
+    fn run_captures(extra_args: Vec<&str>) -> String {
+        let args = [vec!["--highlights", CAPTURES_HIGHLIGHTS], extra_args].concat();
+        run_with_args("fn f() { let s = \"x\"; } // c", "rust", args)
+    }
+
+    #[test]
+    fn test_capture_filters() {
+        assert_eq!(
+            run_captures(vec![
+                "--include-captures",
+                "keyword*",
+                "--include-captures",
+                "*.name",
+                "--exclude-captures",
+                "keyword.?unction",
+            ]),
+            "function.name 3 4\nkeyword 9 12\n"
+        );
+    }
+
+    #[test]
+    fn test_group_by_match() {
+        // the matches keep the pattern index, and the match of pattern 2 is skipped since all its captures were
+        // excluded
+        assert_eq!(
+            run_captures(vec!["--group-by", "match", "--exclude-captures", "keyword"]),
+            r#"match 1
+  keyword.function 0 2
+match 0
+  function.name 3 4
+  scope 7 23
+match 3
+  string 17 20
+match 4
+  comment 24 28
+"#
+        );
+    }
+
+    #[test]
+    fn test_capture_details() {
+        assert_eq!(
+            run_captures(vec![
+                "--include-captures",
+                "keyword*",
+                "--include-captures",
+                "function.name",
+                "--include-captures",
+                "string",
+                "--capture-kind",
+                "--capture-field",
+                "--capture-text",
+                "--capture-max-text",
+                "2",
+            ]),
+            r#"keyword.function 0 2 kind:"fn" text:"fn"
+function.name 3 4 kind:identifier field:name text:"f"
+keyword 9 12 kind:"let" text:"le..."
+string 17 20 kind:string_literal field:value text:"\"x..."
+"#
+        );
+    }
+
+    #[test]
+    fn test_capture_details_conflict_with_tree_and_graphviz() {
+        for flag in ["--capture-kind", "--capture-field", "--capture-text"] {
+            for output in [
+                vec!["--tree", "sexp"],
+                vec!["--graphviz-only"],
+                vec!["--render", "svg", "--output", "tree.svg"],
+            ] {
+                let mut args = vec!["main", "--code", "x", "--language", "rust", flag];
+                assert!(get_command().try_get_matches_from(args.clone()).is_ok());
+                args.extend(output);
+                assert!(get_command().try_get_matches_from(args).is_err());
+            }
+        }
+    }
 }
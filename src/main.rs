@@ -1,13 +1,68 @@
+mod ansi;
+mod binary;
+mod config;
+mod diagnostics;
+mod elisp;
 mod graphviz;
+mod html;
+mod json;
 mod languages;
+mod navigation;
+mod predicates;
+mod queries;
+mod symbols;
+mod text;
+mod textobjects;
+mod theme;
+mod tree;
 
-use crate::graphviz::generate_dot_graph;
-use crate::languages::{map_language_to_enum, process_query, set_parser_language, LANGUAGES};
+use crate::ansi::process_ansi;
+use crate::binary::process_binary;
+use crate::config::{CaptureMap, Config};
+use crate::diagnostics::process_diagnostics;
+use crate::elisp::process_elisp;
+use crate::graphviz::{
+    generate_dot_graph, generate_mermaid_graph, generate_plantuml_graph, render_dot_graph,
+    GraphvizOptions, DEFAULT_LABEL_TEMPLATE, DEFAULT_MAX_TEXT_LENGTH, RANKDIRS, RENDER_FORMATS,
+};
+use crate::html::{process_html, HtmlOptions};
+use crate::languages::{
+    collect_captures, create_query, map_capture_names, map_language_to_enum, process_query,
+    resolve_spans, set_parser_language, CaptureFilter, QueryOutputOptions, LANGUAGES,
+};
+use crate::navigation::{
+    parse_byte_range, parse_point, process_expand_selection, process_node_at,
+    process_shrink_selection, Location,
+};
+use crate::queries::{builtin_query, resolve_inherits, QueryPaths};
+use crate::symbols::process_symbols;
+use crate::textobjects::{process_textobject, DIRECTIONS};
+use crate::theme::{Theme, COLOR_DEPTHS, DEFAULT_THEME};
+use crate::tree::{process_tree, TreeOptions, TREE_FORMATS};
 use clap::{Arg, ArgAction, ArgMatches};
+use std::collections::HashMap;
 use std::io;
 use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::process::exit;
-use tree_sitter::Parser;
+use tree_sitter::{Parser, Point, Tree};
+
+pub static MODES: [&str; 7] = [
+    "highlights",
+    "symbols",
+    "textobject",
+    "node-at",
+    "expand-selection",
+    "shrink-selection",
+    "diagnostics",
+];
+
+/// How the captures of `--mode highlights` are output.
+pub static FORMATS: [&str; 5] = ["captures", "ansi", "html", "binary", "elisp"];
+
+/// How the captures of `--format captures` are grouped.
+pub static GROUP_BY: [&str; 2] = ["capture", "match"];
 
 pub fn get_command() -> clap::Command {
     clap::Command::new("Tree-sitter Syntax Highlighter")
@@ -26,51 +81,495 @@ pub fn get_command() -> clap::Command {
                 .value_parser(LANGUAGES)
                 .required(true),
         )
+        .arg(
+            Arg::new("mode")
+                .long("mode")
+                .value_parser(MODES)
+                .default_value("highlights")
+                .help("What to output, defaults to capture names with byte ranges of --highlights"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(FORMATS)
+                .default_value("captures")
+                .help("The output of --mode highlights, either capture names with byte ranges, the code colored by --theme for the terminal or HTML, the captures in a compact binary format or as Emacs Lisp vector with 1-based character positions"),
+        )
+        .arg(
+            Arg::new("include-captures")
+                .long("include-captures")
+                .action(ArgAction::Append)
+                .help("Only output captures whose name matches this glob pattern like keyword.* where * matches any characters and ? one. Can be repeated"),
+        )
+        .arg(
+            Arg::new("exclude-captures")
+                .long("exclude-captures")
+                .action(ArgAction::Append)
+                .help("Do not output captures whose name matches this glob pattern, which takes precedence over --include-captures. Can be repeated"),
+        )
+        .arg(
+            Arg::new("group-by")
+                .long("group-by")
+                .value_parser(GROUP_BY)
+                .default_value("capture")
+                .help("Output one capture per line, or group the captures of each query match after a line with the pattern index for --format captures"),
+        )
+        .arg(
+            Arg::new("capture-kind")
+                .long("capture-kind")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["tree", "graphviz-only", "render"])
+                .help("Append the node kind like kind:identifier to the lines of --format captures"),
+        )
+        .arg(
+            Arg::new("capture-field")
+                .long("capture-field")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["tree", "graphviz-only", "render"])
+                .help("Append the field name of the node in its parent like field:name to the lines of --format captures"),
+        )
+        .arg(
+            Arg::new("capture-text")
+                .long("capture-text")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["tree", "graphviz-only", "render"])
+                .help("Append the source text of the node as JSON string like text:\"main\" to the lines of --format captures"),
+        )
+        .arg(
+            Arg::new("capture-max-text")
+                .long("capture-max-text")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("60")
+                .help("Truncate the text of --capture-text to this many characters"),
+        )
+        .arg(
+            Arg::new("theme")
+                .long("theme")
+                .help("Path to a JSON theme file mapping capture names to colors for --format ansi and html, like {\"keyword\": \"#c678dd\"}"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .help("Path to a TOML file which renames capture names for all output formats, globally in [captures] and per language like [languages.kotlin.captures], and can contain a [theme] and [queries] directories per language"),
+        )
+        .arg(
+            Arg::new("color-depth")
+                .long("color-depth")
+                .value_parser(COLOR_DEPTHS)
+                .default_value("truecolor")
+                .help("The colors of the terminal for --format ansi"),
+        )
+        .arg(
+            Arg::new("html-line-numbers")
+                .long("html-line-numbers")
+                .action(ArgAction::SetTrue)
+                .help("Prefix every line of --format html with its line number"),
+        )
+        .arg(
+            Arg::new("html-inline-styles")
+                .long("html-inline-styles")
+                .action(ArgAction::SetTrue)
+                .help("Add the --theme styles as style attributes to the spans of --format html"),
+        )
+        .arg(
+            Arg::new("html-standalone")
+                .long("html-standalone")
+                .action(ArgAction::SetTrue)
+                .help("Wrap --format html in a page with a stylesheet generated from --theme"),
+        )
         .arg(
             Arg::new("highlights")
                 .long("highlights")
-                .help("String of highlights like the content of queries/highlights.scm. This is required when not using --graphviz-only")
+                .help("String of highlights like the content of queries/highlights.scm. Defaults to highlights.scm of the language in --query-dir or the query of the grammar crate")
         )
         .arg(
             Arg::new("highlights-file")
                 .long("highlights-file")
                 .help("Path to a highlights file (e.g., queries/highlights.scm). Alternative to --highlights.")
         )
+        .arg(
+            Arg::new("query-dir")
+                .long("query-dir")
+                .action(ArgAction::Append)
+                .help("Directory with a subdirectory of queries per language like queries/kotlin/highlights.scm, used when a query like --highlights is not passed and for the languages of ; inherits: lines. Can be repeated"),
+        )
+        .arg(
+            Arg::new("tags")
+                .long("tags")
+                .help("String of tags like the content of queries/tags.scm. This is required for --mode symbols")
+        )
+        .arg(
+            Arg::new("tags-file")
+                .long("tags-file")
+                .help("Path to a tags file (e.g., queries/tags.scm). Alternative to --tags.")
+        )
+        .arg(
+            Arg::new("textobjects")
+                .long("textobjects")
+                .help("String of text objects like the content of queries/textobjects.scm. This is required for --mode textobject")
+        )
+        .arg(
+            Arg::new("textobjects-file")
+                .long("textobjects-file")
+                .help("Path to a text objects file (e.g., queries/textobjects.scm). Alternative to --textobjects.")
+        )
+        .arg(
+            Arg::new("object")
+                .long("object")
+                .help("The text object capture name for --mode textobject, like function.outer or parameter.inner")
+        )
+        .arg(
+            Arg::new("position")
+                .long("position")
+                .value_parser(clap::value_parser!(usize))
+                .help("Byte offset in --code")
+        )
+        .arg(
+            Arg::new("point")
+                .long("point")
+                .value_parser(parse_point)
+                .conflicts_with("position")
+                .help("0-based row:column in --code, the column is in bytes. Alternative to --position for --mode node-at")
+        )
+        .arg(
+            Arg::new("range")
+                .long("range")
+                .value_parser(parse_byte_range)
+                .help("The current selection as start:end byte offsets for --mode expand-selection and shrink-selection")
+        )
+        .arg(
+            Arg::new("include-anonymous")
+                .long("include-anonymous")
+                .action(ArgAction::SetTrue)
+                .help("Also consider anonymous nodes like punctuation for --mode node-at, expand-selection and shrink-selection, and output them for --tree")
+        )
+        .arg(
+            Arg::new("direction")
+                .long("direction")
+                .value_parser(DIRECTIONS)
+                .default_value("current")
+                .help("Select the text object around --position or navigate to the next or previous one")
+        )
+        .arg(
+            Arg::new("count")
+                .long("count")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1")
+                .help("How many text objects to move with --direction next or previous")
+        )
+        .arg(
+            Arg::new("tree")
+                .long("tree")
+                .value_parser(TREE_FORMATS)
+                .help("Output the whole syntax tree in this format instead"),
+        )
+        .arg(
+            Arg::new("tree-fields")
+                .long("tree-fields")
+                .action(ArgAction::SetTrue)
+                .help("Prefix the nodes of --tree sexp with their field name like name: or body:"),
+        )
+        .arg(
+            Arg::new("tree-ranges")
+                .long("tree-ranges")
+                .action(ArgAction::SetTrue)
+                .help("Include the byte ranges and row/column positions of the nodes in --tree sexp"),
+        )
+        .arg(
+            Arg::new("tree-text")
+                .long("tree-text")
+                .action(ArgAction::SetTrue)
+                .help("Include the source text of leaf nodes in --tree json and elisp"),
+        )
         .arg(
             Arg::new("graphviz-only")
                 .long("graphviz-only")
                 .action(ArgAction::SetTrue)
                 .help("If passed, output only the graphviz dot graph"),
         )
+        .arg(
+            Arg::new("graphviz-anonymous-style")
+                .long("graphviz-anonymous-style")
+                .action(ArgAction::SetTrue)
+                .help("Render anonymous nodes like punctuation grey and dashed in the graphviz dot graph"),
+        )
+        .arg(
+            Arg::new("graphviz-named-only")
+                .long("graphviz-named-only")
+                .action(ArgAction::SetTrue)
+                .help("Omit anonymous nodes like punctuation from the graphviz dot graph, except MISSING nodes"),
+        )
+        .arg(
+            Arg::new("graphviz-max-depth")
+                .long("graphviz-max-depth")
+                .value_parser(clap::value_parser!(usize))
+                .help("Collapse the nodes below this depth into a summary node in the graphviz dot graph"),
+        )
+        .arg(
+            Arg::new("graphviz-focus")
+                .long("graphviz-focus")
+                .value_parser(parse_byte_range)
+                .help("Root the graphviz dot graph at the smallest node covering the start:end byte range"),
+        )
+        .arg(
+            Arg::new("graphviz-errors")
+                .long("graphviz-errors")
+                .action(ArgAction::SetTrue)
+                .help("Highlight ERROR and MISSING nodes and the nodes containing them in the graphviz dot graph, with a legend"),
+        )
+        .arg(
+            Arg::new("graphviz-captures")
+                .long("graphviz-captures")
+                .action(ArgAction::SetTrue)
+                .help("Annotate and color the nodes in the graphviz dot graph by the captures of --highlights, uncaptured nodes are greyed out"),
+        )
+        .arg(
+            Arg::new("graphviz-rankdir")
+                .long("graphviz-rankdir")
+                .value_parser(RANKDIRS)
+                .help("The direction of the graphviz dot graph, like LR for left to right"),
+        )
+        .arg(
+            Arg::new("graphviz-label")
+                .long("graphviz-label")
+                .help(format!(
+                    "The node label in the graphviz dot graph with the placeholders {{kind}}, {{range}}, {{start}}, {{end}}, {{point}}, {{text}} and {{field}}, \\n is a line break [default: {}]",
+                    DEFAULT_LABEL_TEMPLATE.replace('\n', "\\n")
+                )),
+        )
+        .arg(
+            Arg::new("graphviz-max-text")
+                .long("graphviz-max-text")
+                .value_parser(clap::value_parser!(usize))
+                .help(format!(
+                    "Truncate the node text in the graphviz dot graph to this many characters [default: {}]",
+                    DEFAULT_MAX_TEXT_LENGTH
+                )),
+        )
+        .arg(
+            Arg::new("graphviz-collapse-whitespace")
+                .long("graphviz-collapse-whitespace")
+                .action(ArgAction::SetTrue)
+                .help("Collapse newlines and indentation in the node text of the graphviz dot graph into single spaces"),
+        )
+        .arg(
+            Arg::new("render")
+                .long("render")
+                .value_parser(RENDER_FORMATS)
+                .requires("output")
+                .help("Render the graphviz dot graph into --output via the dot executable of Graphviz, implies --graphviz-only"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .requires("render")
+                .help("The file path to render the graphviz dot graph into"),
+        )
+        .arg(
+            Arg::new("dot-arg")
+                .long("dot-arg")
+                .action(ArgAction::Append)
+                .allow_hyphen_values(true)
+                .requires("render")
+                .help("Argument passed through to dot for --render, like -Gsplines=ortho. Can be repeated"),
+        )
 }
 
-pub fn handle_args<W>(args: ArgMatches, mut writer: W)
-where
-    W: Write,
-{
-    let code = args.get_one::<String>("code").unwrap();
+/// Reads a query passed either as string via `--{name}` or as path via `--{name}-file`, or `{name}.scm` of the
+/// language from the `--config` query dirs or `--query-dir`. The queries of its `; inherits:` lines are prepended.
+fn read_query_arg(args: &ArgMatches, config: &Config, name: &str) -> Option<String> {
+    let file_arg = format!("{}-file", name);
+    let query = args.get_one::<String>(name);
+    let query_file = args.get_one::<String>(&file_arg);
+    let query_paths = QueryPaths {
+        overrides: config.query_dirs.clone(),
+        query_dirs: args
+            .get_many::<String>("query-dir")
+            .unwrap_or_default()
+            .map(PathBuf::from)
+            .collect(),
+    };
     let language = args.get_one::<String>("language").unwrap();
-    let graphviz_only = args.get_one::<bool>("graphviz-only").unwrap();
-    let highlights = args.get_one::<String>("highlights");
-    let highlights_file = args.get_one::<String>("highlights-file");
-
-    let highlights_content = if highlights.is_some() && highlights_file.is_some() {
-        eprintln!("Error: Cannot use both --highlights and --highlights-file simultaneously");
+    let file_path = if query.is_some() && query_file.is_some() {
+        eprintln!(
+            "Error: Cannot use both --{} and --{} simultaneously",
+            name, file_arg
+        );
         exit(1);
-    } else if let Some(file_path) = highlights_file {
-        match std::fs::read_to_string(file_path) {
+    } else if let Some(file_path) = query_file {
+        Some(PathBuf::from(file_path))
+    } else if query.is_none() {
+        query_paths.find(language, &format!("{}.scm", name))
+    } else {
+        None
+    };
+    let query = match &file_path {
+        Some(file_path) => match std::fs::read_to_string(file_path) {
             Ok(content) => content,
             Err(e) => {
-                eprintln!("Error reading highlights file '{}': {}", file_path, e);
+                eprintln!(
+                    "Error reading {} file '{}': {}",
+                    name,
+                    file_path.display(),
+                    e
+                );
                 exit(1);
             }
-        }
-    } else if let Some(h) = highlights {
-        h.clone()
-    } else {
-        eprintln!("--highlights or --highlights-file is required when not using --graphviz-only");
+        },
+        None => query?.clone(),
+    };
+    let query =
+        resolve_inherits(&query, name, file_path.as_deref(), &query_paths).unwrap_or_else(|e| {
+            eprintln!("Error resolving the inherits of the {} query: {}", name, e);
+            exit(1);
+        });
+    Some(query)
+}
+
+/// Like `read_query_arg()`, but falls back to the query of the grammar crate and exits when there is none.
+/// `required_for` completes the error message, like `for --mode symbols`.
+fn require_query_arg(args: &ArgMatches, config: &Config, name: &str, required_for: &str) -> String {
+    let language = args.get_one::<String>("language").unwrap();
+    read_query_arg(args, config, name)
+        .or_else(|| builtin_query(language, name).map(str::to_string))
+        .unwrap_or_else(|| {
+            eprintln!(
+                "--{} or --{}-file is required {}, or {}.scm of the language in --query-dir",
+                name, name, required_for, name
+            );
+            exit(1);
+        })
+}
+
+/// The `--config` file, or an empty config. Relative query dirs are resolved against the directory of the config
+/// file.
+fn read_config(args: &ArgMatches) -> Config {
+    let Some(file_path) = args.get_one::<String>("config") else {
+        return Config::default();
+    };
+    let config = std::fs::read_to_string(file_path).unwrap_or_else(|e| {
+        eprintln!("Error reading config file '{}': {}", file_path, e);
+        exit(1);
+    });
+    let mut config = Config::parse(&config).unwrap_or_else(|e| {
+        eprintln!("Error parsing config file '{}': {}", file_path, e);
         exit(1);
+    });
+    let config_dir = Path::new(file_path).parent().unwrap_or(Path::new(""));
+    for dir in config.query_dirs.values_mut() {
+        *dir = config_dir.join(&dir);
+    }
+    config
+}
+
+/// The theme of `--theme`, the `[theme]` of `--config` or the default theme.
+fn read_theme(args: &ArgMatches, config: &Config) -> Theme {
+    let theme = match args.get_one::<String>("theme") {
+        Some(file_path) => std::fs::read_to_string(file_path).unwrap_or_else(|e| {
+            eprintln!("Error reading theme file '{}': {}", file_path, e);
+            exit(1);
+        }),
+        None => match &config.theme {
+            Some(theme) => return theme.clone(),
+            None => DEFAULT_THEME.to_string(),
+        },
     };
+    Theme::parse(&theme).unwrap_or_else(|e| {
+        eprintln!("Error parsing theme: {}", e);
+        exit(1);
+    })
+}
+
+/// The `--include-captures` and `--exclude-captures` patterns.
+fn capture_filter(args: &ArgMatches) -> CaptureFilter {
+    let patterns = |name: &str| {
+        args.get_many::<String>(name)
+            .unwrap_or_default()
+            .cloned()
+            .collect()
+    };
+    CaptureFilter {
+        include: patterns("include-captures"),
+        exclude: patterns("exclude-captures"),
+    }
+}
+
+/// The deduplicated capture names of the highlights query by node ID, without the `_` helper captures of
+/// predicates like `resolve_spans()`.
+fn capture_names_by_node(
+    parser: &Parser,
+    highlights: &str,
+    capture_map: &CaptureMap,
+    capture_filter: &CaptureFilter,
+    language: &str,
+    tree: &Tree,
+    code: &str,
+) -> HashMap<usize, Vec<String>> {
+    let query = create_query(parser, highlights);
+    let capture_names = map_capture_names(&query, capture_map, capture_filter, language);
+    let mut captures: HashMap<usize, Vec<String>> = HashMap::new();
+    for capture in collect_captures(&query, &capture_names, tree, code) {
+        if capture.name.starts_with('_') {
+            continue;
+        }
+        let capture_names = captures.entry(capture.node.id()).or_default();
+        if !capture_names.iter().any(|name| name == capture.name) {
+            capture_names.push(capture.name.to_string());
+        }
+    }
+    captures
+}
+
+/// The `--graphviz-*` options, which also apply to `--tree mermaid` and `plantuml`.
+fn graphviz_options(
+    args: &ArgMatches,
+    parser: &Parser,
+    config: &Config,
+    tree: &Tree,
+    code: &str,
+) -> GraphvizOptions {
+    GraphvizOptions {
+        style_anonymous: *args.get_one::<bool>("graphviz-anonymous-style").unwrap(),
+        named_only: *args.get_one::<bool>("graphviz-named-only").unwrap(),
+        max_depth: args.get_one::<usize>("graphviz-max-depth").copied(),
+        focus: args.get_one::<Range<usize>>("graphviz-focus").cloned(),
+        highlight_errors: *args.get_one::<bool>("graphviz-errors").unwrap(),
+        captures: if *args.get_one::<bool>("graphviz-captures").unwrap() {
+            let highlights =
+                require_query_arg(args, config, "highlights", "for --graphviz-captures");
+            let language = args.get_one::<String>("language").unwrap();
+            Some(capture_names_by_node(
+                parser,
+                &highlights,
+                &config.capture_map,
+                &capture_filter(args),
+                language,
+                tree,
+                code,
+            ))
+        } else {
+            None
+        },
+        rankdir: args.get_one::<String>("graphviz-rankdir").cloned(),
+        label_template: args
+            .get_one::<String>("graphviz-label")
+            .map(|template| template.replace("\\n", "\n")),
+        max_text_length: args.get_one::<usize>("graphviz-max-text").copied(),
+        collapse_whitespace: *args
+            .get_one::<bool>("graphviz-collapse-whitespace")
+            .unwrap(),
+    }
+}
+
+pub fn handle_args<W>(args: ArgMatches, mut writer: W)
+where
+    W: Write,
+{
+    let code = args.get_one::<String>("code").unwrap();
+    let language = args.get_one::<String>("language").unwrap();
+    let mode = args.get_one::<String>("mode").unwrap();
+    let graphviz_only = args.get_one::<bool>("graphviz-only").unwrap();
+    let render = args.get_one::<String>("render");
 
     let mut parser = Parser::new();
     let language_enum = map_language_to_enum(language);
@@ -82,11 +581,208 @@ where
         code.clone()
     };
     let tree = parser.parse(&code, None).unwrap();
-    if *graphviz_only {
-        write!(writer, "{}", generate_dot_graph(&tree, &code))
-            .expect("writing dot graph should succeed");
-    } else {
-        process_query(parser, &highlights_content, &tree, &code, &mut writer);
+    let config = read_config(&args);
+    if let Some(format) = args.get_one::<String>("tree") {
+        let options = TreeOptions {
+            include_anonymous: *args.get_one::<bool>("include-anonymous").unwrap(),
+            fields: *args.get_one::<bool>("tree-fields").unwrap(),
+            ranges: *args.get_one::<bool>("tree-ranges").unwrap(),
+            text: *args.get_one::<bool>("tree-text").unwrap(),
+            captures: if format == "html" {
+                read_query_arg(&args, &config, "highlights").map(|highlights| {
+                    capture_names_by_node(
+                        &parser,
+                        &highlights,
+                        &config.capture_map,
+                        &capture_filter(&args),
+                        language,
+                        &tree,
+                        &code,
+                    )
+                })
+            } else {
+                None
+            },
+        };
+        match format.as_str() {
+            "mermaid" => {
+                let options = graphviz_options(&args, &parser, &config, &tree, &code);
+                write!(writer, "{}", generate_mermaid_graph(&tree, &code, &options))
+                    .expect("write should succeed");
+            }
+            "plantuml" => {
+                let options = graphviz_options(&args, &parser, &config, &tree, &code);
+                write!(
+                    writer,
+                    "{}",
+                    generate_plantuml_graph(&tree, &code, &options)
+                )
+                .expect("write should succeed");
+            }
+            _ => process_tree(format, &tree, &code, &options, &mut writer),
+        }
+        return;
+    }
+    if *graphviz_only || render.is_some() {
+        let options = graphviz_options(&args, &parser, &config, &tree, &code);
+        let dot_graph = generate_dot_graph(&tree, &code, &options);
+        if let Some(format) = render {
+            let output_path = args.get_one::<String>("output").unwrap();
+            let dot_args: Vec<String> = args
+                .get_many::<String>("dot-arg")
+                .unwrap_or_default()
+                .cloned()
+                .collect();
+            if let Err(e) = render_dot_graph(&dot_graph, format, output_path, &dot_args) {
+                eprintln!("Error rendering the graphviz dot graph: {}", e);
+                exit(1);
+            }
+        } else {
+            write!(writer, "{}", dot_graph).expect("writing dot graph should succeed");
+        }
+        return;
+    }
+    match mode.as_str() {
+        "symbols" => {
+            let tags = require_query_arg(&args, &config, "tags", &format!("for --mode {}", mode));
+            process_symbols(parser, &tags, &tree, &code, &mut writer);
+        }
+        "textobject" => {
+            let textobjects = require_query_arg(
+                &args,
+                &config,
+                "textobjects",
+                &format!("for --mode {}", mode),
+            );
+            let Some(object) = args.get_one::<String>("object") else {
+                eprintln!("--object is required for --mode textobject");
+                exit(1);
+            };
+            let Some(position) = args.get_one::<usize>("position") else {
+                eprintln!("--position is required for --mode textobject");
+                exit(1);
+            };
+            process_textobject(
+                parser,
+                &textobjects,
+                object,
+                *position,
+                args.get_one::<String>("direction").unwrap(),
+                *args.get_one::<usize>("count").unwrap(),
+                &tree,
+                &code,
+                &mut writer,
+            );
+        }
+        "node-at" => {
+            let location = if let Some(point) = args.get_one::<Point>("point") {
+                Location::Point(*point)
+            } else if let Some(position) = args.get_one::<usize>("position") {
+                Location::Byte(*position)
+            } else {
+                eprintln!("--position or --point is required for --mode node-at");
+                exit(1);
+            };
+            process_node_at(
+                location,
+                *args.get_one::<bool>("include-anonymous").unwrap(),
+                &tree,
+                &code,
+                &mut writer,
+            );
+        }
+        "expand-selection" | "shrink-selection" => {
+            let Some(selection) = args.get_one::<Range<usize>>("range") else {
+                eprintln!("--range is required for --mode {}", mode);
+                exit(1);
+            };
+            let include_anonymous = *args.get_one::<bool>("include-anonymous").unwrap();
+            if mode == "expand-selection" {
+                process_expand_selection(selection.clone(), include_anonymous, &tree, &mut writer);
+            } else {
+                // without --position, shrink towards the start of the selection
+                let origin = args
+                    .get_one::<usize>("position")
+                    .copied()
+                    .unwrap_or(selection.start);
+                process_shrink_selection(
+                    selection.clone(),
+                    origin,
+                    include_anonymous,
+                    &tree,
+                    &mut writer,
+                );
+            }
+        }
+        "diagnostics" => process_diagnostics(&tree, &code, &mut writer),
+        _ => {
+            let highlights = require_query_arg(
+                &args,
+                &config,
+                "highlights",
+                &format!("for --mode {}", mode),
+            );
+            let query = create_query(&parser, &highlights);
+            let capture_names = map_capture_names(
+                &query,
+                &config.capture_map,
+                &capture_filter(&args),
+                language,
+            );
+            let format = args.get_one::<String>("format").unwrap();
+            let options = QueryOutputOptions {
+                group_by_match: args.get_one::<String>("group-by").unwrap() == "match",
+                kind: *args.get_one::<bool>("capture-kind").unwrap(),
+                field: *args.get_one::<bool>("capture-field").unwrap(),
+                max_text_length: if *args.get_one::<bool>("capture-text").unwrap() {
+                    args.get_one::<usize>("capture-max-text").copied()
+                } else {
+                    None
+                },
+            };
+            if options.group_by_match && format != "captures" {
+                eprintln!("--group-by match is only supported for --format captures");
+                exit(1);
+            }
+            if (options.kind || options.field || options.max_text_length.is_some())
+                && format != "captures"
+            {
+                eprintln!(
+                    "--capture-kind, --capture-field and --capture-text are only supported for --format captures"
+                );
+                exit(1);
+            }
+            match format.as_str() {
+                "ansi" => {
+                    let spans =
+                        resolve_spans(&collect_captures(&query, &capture_names, &tree, &code));
+                    let color_depth = args.get_one::<String>("color-depth").unwrap();
+                    let theme = read_theme(&args, &config);
+                    process_ansi(&spans, &code, &theme, color_depth, &mut writer);
+                }
+                "html" => {
+                    let spans =
+                        resolve_spans(&collect_captures(&query, &capture_names, &tree, &code));
+                    let options = HtmlOptions {
+                        line_numbers: *args.get_one::<bool>("html-line-numbers").unwrap(),
+                        inline_styles: *args.get_one::<bool>("html-inline-styles").unwrap(),
+                        standalone: *args.get_one::<bool>("html-standalone").unwrap(),
+                    };
+                    let theme = read_theme(&args, &config);
+                    process_html(&spans, &code, &theme, &options, &mut writer);
+                }
+                "binary" => process_binary(
+                    &collect_captures(&query, &capture_names, &tree, &code),
+                    &mut writer,
+                ),
+                "elisp" => process_elisp(
+                    &collect_captures(&query, &capture_names, &tree, &code),
+                    &code,
+                    &mut writer,
+                ),
+                _ => process_query(&query, &capture_names, &options, &tree, &code, &mut writer),
+            }
+        }
     }
 }
 
@@ -0,0 +1,292 @@
+use crate::json::range_to_json;
+use crate::text::truncate;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::ops::Range;
+use tree_sitter::{Node, Point, Tree};
+
+const MAX_TEXT_LENGTH: usize = 60;
+
+/// Parses `row:column` with a 0-based row and a 0-based byte column like tree-sitter's `Point`.
+pub fn parse_point(value: &str) -> Result<Point, String> {
+    let (row, column) = value
+        .split_once(':')
+        .ok_or_else(|| format!("'{}' is not in the format row:column", value))?;
+    let row = row
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid row", row))?;
+    let column = column
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid column", column))?;
+    Ok(Point { row, column })
+}
+
+/// Parses `start:end` byte offsets.
+pub fn parse_byte_range(value: &str) -> Result<Range<usize>, String> {
+    let (start, end) = value
+        .split_once(':')
+        .ok_or_else(|| format!("'{}' is not in the format start:end", value))?;
+    let start = start
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid start byte", start))?;
+    let end = end
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid end byte", end))?;
+    if start > end {
+        return Err(format!("start {} is after end {}", start, end));
+    }
+    Ok(start..end)
+}
+
+/// Where to look up a node, either `--position` or `--point`.
+pub enum Location {
+    Byte(usize),
+    Point(Point),
+}
+
+/// The field name of the node in its parent, like `name` or `body`.
+pub fn field_name(node: Node) -> Option<&'static str> {
+    let parent = node.parent()?;
+    let mut cursor = parent.walk();
+    if !cursor.goto_first_child() {
+        return None;
+    }
+    loop {
+        if cursor.node() == node {
+            return cursor.field_name();
+        }
+        if !cursor.goto_next_sibling() {
+            return None;
+        }
+    }
+}
+
+fn node_to_json(node: Node) -> Value {
+    json!({
+        "kind": node.kind(),
+        "named": node.is_named(),
+        "field_name": field_name(node),
+        "range": range_to_json(node.range()),
+    })
+}
+
+/// Outputs a JSON object with the `kind`, `named` flag, `field_name`, `range` and truncated `text` of the
+/// smallest (named) node at the location, and the `ancestors` from the parent up to the root.
+pub fn process_node_at<W>(
+    location: Location,
+    include_anonymous: bool,
+    tree: &Tree,
+    code: &str,
+    writer: &mut W,
+) where
+    W: Write,
+{
+    let root = tree.root_node();
+    let node = match (location, include_anonymous) {
+        (Location::Byte(byte), true) => root.descendant_for_byte_range(byte, byte),
+        (Location::Byte(byte), false) => root.named_descendant_for_byte_range(byte, byte),
+        (Location::Point(point), true) => root.descendant_for_point_range(point, point),
+        (Location::Point(point), false) => root.named_descendant_for_point_range(point, point),
+    }
+    .unwrap_or(root);
+
+    let mut output = node_to_json(node);
+    output["text"] = json!(truncate(&code[node.byte_range()], MAX_TEXT_LENGTH));
+    let mut ancestors = Vec::new();
+    let mut ancestor = node.parent();
+    while let Some(parent) = ancestor {
+        ancestors.push(node_to_json(parent));
+        ancestor = parent.parent();
+    }
+    output["ancestors"] = Value::Array(ancestors);
+    writeln!(writer, "{}", output).expect("write should succeed");
+}
+
+fn write_node_range<W>(node: Node, writer: &mut W)
+where
+    W: Write,
+{
+    writeln!(
+        writer,
+        "{} {} {}",
+        node.kind(),
+        node.start_byte(),
+        node.end_byte()
+    )
+    .expect("write should succeed");
+}
+
+/// Outputs `{kind} {byteRangeStart} {byteRangeEnd}` of the smallest (named) node which is larger than the
+/// selection and covers it. Nothing is output when the selection already covers the root node.
+pub fn process_expand_selection<W>(
+    selection: Range<usize>,
+    include_anonymous: bool,
+    tree: &Tree,
+    writer: &mut W,
+) where
+    W: Write,
+{
+    let root = tree.root_node();
+    let mut node = if include_anonymous {
+        root.descendant_for_byte_range(selection.start, selection.end)
+    } else {
+        root.named_descendant_for_byte_range(selection.start, selection.end)
+    };
+    // wrapper nodes like expression_statement often have the exact same range as their child
+    while let Some(current) = node {
+        if current.byte_range() != selection {
+            break;
+        }
+        node = current.parent();
+    }
+    if let Some(node) = node {
+        write_node_range(node, writer);
+    }
+}
+
+/// Outputs `{kind} {byteRangeStart} {byteRangeEnd}` of the (named) child of the node covering the selection
+/// which contains `origin` or is the closest to it, usually the cursor position from before expanding.
+/// Children with the same range as the selection are descended into. Nothing is output for leaf nodes.
+pub fn process_shrink_selection<W>(
+    selection: Range<usize>,
+    origin: usize,
+    include_anonymous: bool,
+    tree: &Tree,
+    writer: &mut W,
+) where
+    W: Write,
+{
+    let root = tree.root_node();
+    let Some(mut node) = (if include_anonymous {
+        root.descendant_for_byte_range(selection.start, selection.end)
+    } else {
+        root.named_descendant_for_byte_range(selection.start, selection.end)
+    }) else {
+        return;
+    };
+    loop {
+        let mut cursor = node.walk();
+        let children: Vec<Node> = if include_anonymous {
+            node.children(&mut cursor).collect()
+        } else {
+            node.named_children(&mut cursor).collect()
+        };
+        let distance = |child: &Node| {
+            if origin < child.start_byte() {
+                child.start_byte() - origin
+            } else {
+                origin.saturating_sub(child.end_byte().saturating_sub(1))
+            }
+        };
+        let Some(closest) = children.into_iter().min_by_key(distance) else {
+            return;
+        };
+        if closest.byte_range() != selection && closest.byte_range() != node.byte_range() {
+            write_node_range(closest, writer);
+            return;
+        }
+        node = closest;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args;
+    use serde_json::Value;
+
+    fn run_selection(mode: &str, code: &str, selection: &str, extra_args: Vec<&str>) -> String {
+        let args = [vec!["--mode", mode, "--range", selection], extra_args].concat();
+        run_with_args(code, "rust", args)
+    }
+
+    fn run_node_at(code: &str, language: &str, location_args: Vec<&str>) -> Value {
+        let args = [vec!["--mode", "node-at"], location_args].concat();
+        let output = run_with_args(code, language, args);
+        serde_json::from_str(&output).expect("Output should be valid JSON")
+    }
+
+    #[test]
+    fn test_node_at_position() {
+        let node = run_node_at("fn main() { let x = 1; }", "rust", vec!["--position", "16"]);
+        assert_eq!(node["kind"], "identifier");
+        assert_eq!(node["field_name"], "pattern");
+        assert_eq!(node["text"], "x");
+        assert_eq!(node["range"]["start"], 16);
+        assert_eq!(node["range"]["end"], 17);
+        let ancestors: Vec<_> = node["ancestors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|ancestor| ancestor["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            ancestors,
+            vec!["let_declaration", "block", "function_item", "source_file"]
+        );
+        assert_eq!(node["ancestors"][1]["field_name"], "body");
+        assert_eq!(node["ancestors"][3]["field_name"], Value::Null);
+    }
+
+    #[test]
+    fn test_node_at_point_and_anonymous() {
+        let code = "x = 1\ny = \"😄 long\"";
+        let node = run_node_at(code, "python", vec!["--point", "1:0"]);
+        assert_eq!(node["kind"], "identifier");
+        assert_eq!(node["range"]["start"], 6);
+
+        let node = run_node_at(code, "python", vec!["--point", "1:2"]);
+        assert_eq!(node["kind"], "assignment");
+
+        let node = run_node_at(
+            code,
+            "python",
+            vec!["--point", "1:2", "--include-anonymous"],
+        );
+        assert_eq!(node["kind"], "=");
+        assert_eq!(node["named"], false);
+        assert_eq!(node["field_name"], Value::Null);
+    }
+
+    #[test]
+    fn test_expand_selection() {
+        let code = "fn main() { foo(a, b); }";
+        let expand = |selection| run_selection("expand-selection", code, selection, vec![]);
+        // from the cursor in "a" to the identifier
+        assert_eq!(expand("16:16"), "identifier 16 17\n");
+        assert_eq!(expand("16:17"), "arguments 15 21\n");
+        assert_eq!(expand("15:21"), "call_expression 12 21\n");
+        assert_eq!(expand("12:21"), "expression_statement 12 22\n");
+        assert_eq!(expand("12:22"), "block 10 24\n");
+        assert_eq!(expand("0:24"), "");
+        assert_eq!(
+            run_selection(
+                "expand-selection",
+                code,
+                "15:15",
+                vec!["--include-anonymous"]
+            ),
+            "( 15 16\n"
+        );
+    }
+
+    #[test]
+    fn test_shrink_selection() {
+        let code = "fn main() { foo(a, b); }";
+        let shrink = |selection, origin| {
+            run_selection(
+                "shrink-selection",
+                code,
+                selection,
+                vec!["--position", origin],
+            )
+        };
+        assert_eq!(shrink("10:24", "19"), "expression_statement 12 22\n");
+        assert_eq!(shrink("12:22", "19"), "call_expression 12 21\n");
+        assert_eq!(shrink("12:21", "19"), "arguments 15 21\n");
+        assert_eq!(shrink("15:21", "19"), "identifier 19 20\n");
+        assert_eq!(shrink("15:21", "16"), "identifier 16 17\n");
+        // the closest child is used when the origin is outside of all of them
+        assert_eq!(shrink("15:21", "17"), "identifier 16 17\n");
+        assert_eq!(shrink("19:20", "19"), "");
+    }
+}
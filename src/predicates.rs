@@ -0,0 +1,523 @@
+use regex::Regex;
+use std::ops::Range;
+use tree_sitter::{Node, Query, QueryMatch, QueryPredicateArg};
+
+/// The highlight priority of captures without `#set! priority`, the same default as in Neovim.
+pub const DEFAULT_PRIORITY: usize = 100;
+
+/// The check of a predicate on a single captured node.
+enum Check {
+    LuaMatch(Regex),
+    HasAncestor(Vec<String>),
+    HasParent(Vec<String>),
+    KindEq(Vec<String>),
+    Contains(Vec<String>),
+}
+
+impl Check {
+    fn matches(&self, node: Node, code: &str) -> bool {
+        match self {
+            Check::LuaMatch(regex) => regex.is_match(&code[node.byte_range()]),
+            Check::HasAncestor(kinds) => {
+                let mut ancestor = node.parent();
+                while let Some(parent) = ancestor {
+                    if kinds.iter().any(|kind| kind == parent.kind()) {
+                        return true;
+                    }
+                    ancestor = parent.parent();
+                }
+                false
+            }
+            Check::HasParent(kinds) => node
+                .parent()
+                .is_some_and(|parent| kinds.iter().any(|kind| kind == parent.kind())),
+            Check::KindEq(kinds) => kinds.iter().any(|kind| kind == node.kind()),
+            Check::Contains(strings) => {
+                let text = &code[node.byte_range()];
+                strings.iter().any(|string| text.contains(string.as_str()))
+            }
+        }
+    }
+}
+
+/// A predicate like `(#not-has-parent? @variable field_expression)`.
+struct Predicate {
+    capture_index: u32,
+    check: Check,
+    negate: bool,
+    /// With the `any-` prefix, one node of a quantified capture needs to match instead of all of them.
+    any: bool,
+}
+
+/// An `(#offset! @capture start_row start_column end_row end_column)` directive.
+struct Offset {
+    capture_index: u32,
+    start: (isize, isize),
+    end: (isize, isize),
+}
+
+/// The general predicates and directives of nvim-treesitter and Helix queries which the tree-sitter crate
+/// leaves to the application: `#lua-match?`, `#has-ancestor?`, `#has-parent?`, `#kind-eq?`, `#contains?` (each
+/// also with the `not-` and `any-` prefixes) and `#offset!`. Other general predicates are ignored.
+pub struct Predicates {
+    predicates: Vec<Vec<Predicate>>,
+    offsets: Vec<Vec<Offset>>,
+    /// The byte offset of each line start in the code, to apply `#offset!` rows and columns.
+    line_starts: Vec<usize>,
+}
+
+/// Converts a Lua pattern like `^%u[%w_]*$` into a regex, since `#lua-match?` is the Neovim variant of
+/// `#match?`. Lua patterns are not regular for `%b` and `%f` and back references, so these are rejected.
+pub fn lua_pattern_to_regex(pattern: &str) -> Result<String, String> {
+    fn class(letter: char) -> Option<&'static str> {
+        Some(match letter.to_ascii_lowercase() {
+            'a' => "alpha",
+            'c' => "cntrl",
+            'd' => "digit",
+            'g' => "graph",
+            'l' => "lower",
+            'p' => "punct",
+            's' => "space",
+            'u' => "upper",
+            'w' => "alnum",
+            'x' => "xdigit",
+            _ => return None,
+        })
+    }
+    // the class inside of a bracketed set, which negates for uppercase letters like %S
+    let set_class = |letter: char| {
+        class(letter).map(|name| {
+            if letter.is_ascii_uppercase() {
+                format!("[:^{}:]", name)
+            } else {
+                format!("[:{}:]", name)
+            }
+        })
+    };
+    let escape_set_char = |c: char| match c {
+        '\\' | '[' | ']' | '^' | '&' | '~' | '-' => format!("\\{}", c),
+        _ => c.to_string(),
+    };
+
+    let chars: Vec<char> = pattern.chars().collect();
+    // `.` matches newlines in Lua
+    let mut regex = String::from("(?s)");
+    // whether the last item is a single character class which a quantifier can follow
+    let mut quantifiable = false;
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        index += 1;
+        match c {
+            '^' if index == 1 => {
+                regex.push('^');
+                quantifiable = false;
+            }
+            '$' if index == chars.len() => {
+                regex.push('$');
+                quantifiable = false;
+            }
+            '%' => {
+                let Some(&next) = chars.get(index) else {
+                    return Err(format!("'{}' ends with %", pattern));
+                };
+                index += 1;
+                if next == 'b' || next == 'f' || next.is_ascii_digit() {
+                    return Err(format!("%{} in '{}' is not supported", next, pattern));
+                }
+                match set_class(next) {
+                    Some(class) => regex.push_str(&format!("[{}]", class)),
+                    None => regex.push_str(&regex::escape(&next.to_string())),
+                }
+                quantifiable = true;
+            }
+            '[' => {
+                regex.push('[');
+                if chars.get(index) == Some(&'^') {
+                    regex.push('^');
+                    index += 1;
+                }
+                let mut first = true;
+                loop {
+                    let Some(&c) = chars.get(index) else {
+                        return Err(format!("'{}' has an unclosed [", pattern));
+                    };
+                    index += 1;
+                    match c {
+                        // a ] right after [ or [^ is a literal
+                        ']' if !first => break,
+                        '%' => {
+                            let Some(&next) = chars.get(index) else {
+                                return Err(format!("'{}' has an unclosed [", pattern));
+                            };
+                            index += 1;
+                            match set_class(next) {
+                                Some(class) => regex.push_str(&class),
+                                None => regex.push_str(&escape_set_char(next)),
+                            }
+                        }
+                        '-' if !first && chars.get(index) != Some(&']') => regex.push('-'),
+                        _ => regex.push_str(&escape_set_char(c)),
+                    }
+                    first = false;
+                }
+                regex.push(']');
+                quantifiable = true;
+            }
+            '*' | '+' | '?' if quantifiable => {
+                regex.push(c);
+                quantifiable = false;
+            }
+            '-' if quantifiable => {
+                regex.push_str("*?");
+                quantifiable = false;
+            }
+            '.' => {
+                regex.push('.');
+                quantifiable = true;
+            }
+            '(' | ')' => {
+                regex.push(c);
+                quantifiable = false;
+            }
+            _ => {
+                regex.push_str(&regex::escape(&c.to_string()));
+                quantifiable = true;
+            }
+        }
+    }
+    Ok(regex)
+}
+
+fn parse_offset(operator: &str, args: &[QueryPredicateArg]) -> Result<Offset, String> {
+    let usage = || format!("#{} needs a capture and 4 numbers", operator);
+    let [QueryPredicateArg::Capture(capture_index), numbers @ ..] = args else {
+        return Err(usage());
+    };
+    let numbers = numbers
+        .iter()
+        .map(|arg| match arg {
+            QueryPredicateArg::String(number) => number.parse::<isize>().ok(),
+            QueryPredicateArg::Capture(_) => None,
+        })
+        .collect::<Option<Vec<isize>>>()
+        .ok_or_else(usage)?;
+    let [start_row, start_column, end_row, end_column] = numbers[..] else {
+        return Err(usage());
+    };
+    Ok(Offset {
+        capture_index: *capture_index,
+        start: (start_row, start_column),
+        end: (end_row, end_column),
+    })
+}
+
+fn parse_predicate(
+    operator: &str,
+    args: &[QueryPredicateArg],
+) -> Result<Option<Predicate>, String> {
+    let (negate, name) = match operator.strip_prefix("not-") {
+        Some(name) => (true, name),
+        None => (false, operator),
+    };
+    let (any, name) = match name.strip_prefix("any-") {
+        Some(name) => (true, name),
+        None => (false, name),
+    };
+    if ![
+        "lua-match?",
+        "has-ancestor?",
+        "has-parent?",
+        "kind-eq?",
+        "contains?",
+    ]
+    .contains(&name)
+    {
+        return Ok(None);
+    }
+    let usage = || format!("#{} needs a capture and at least one string", operator);
+    let [QueryPredicateArg::Capture(capture_index), strings @ ..] = args else {
+        return Err(usage());
+    };
+    let strings = strings
+        .iter()
+        .map(|arg| match arg {
+            QueryPredicateArg::String(string) => Some(string.to_string()),
+            QueryPredicateArg::Capture(_) => None,
+        })
+        .collect::<Option<Vec<String>>>()
+        .filter(|strings| !strings.is_empty())
+        .ok_or_else(usage)?;
+    let check = match name {
+        "lua-match?" => {
+            let [pattern] = &strings[..] else {
+                return Err(format!("#{} needs a capture and one pattern", operator));
+            };
+            let regex = lua_pattern_to_regex(pattern)?;
+            Check::LuaMatch(Regex::new(&regex).map_err(|e| e.to_string())?)
+        }
+        "has-ancestor?" => Check::HasAncestor(strings),
+        "has-parent?" => Check::HasParent(strings),
+        "kind-eq?" => Check::KindEq(strings),
+        _ => Check::Contains(strings),
+    };
+    Ok(Some(Predicate {
+        capture_index: *capture_index,
+        check,
+        negate,
+        any,
+    }))
+}
+
+impl Predicates {
+    /// Invalid or unsupported predicates like a `#lua-match?` with `%b()` are reported on stderr and treated as
+    /// matching, so the rest of a query still works.
+    pub fn new(query: &Query, code: &str) -> Predicates {
+        let mut predicates = Vec::new();
+        let mut offsets = Vec::new();
+        for pattern_index in 0..query.pattern_count() {
+            let mut pattern_predicates = Vec::new();
+            let mut pattern_offsets = Vec::new();
+            for predicate in query.general_predicates(pattern_index) {
+                let operator = predicate.operator.as_ref();
+                let result = if operator == "offset!" {
+                    parse_offset(operator, &predicate.args)
+                        .map(|offset| pattern_offsets.push(offset))
+                } else {
+                    parse_predicate(operator, &predicate.args)
+                        .map(|predicate| pattern_predicates.extend(predicate))
+                };
+                if let Err(e) = result {
+                    eprintln!(
+                        "Warning: ignoring #{} of query pattern {}: {}",
+                        operator, pattern_index, e
+                    );
+                }
+            }
+            predicates.push(pattern_predicates);
+            offsets.push(pattern_offsets);
+        }
+        let line_starts = std::iter::once(0)
+            .chain(code.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Predicates {
+            predicates,
+            offsets,
+            line_starts,
+        }
+    }
+
+    /// Whether the match satisfies all predicates of its pattern. Captures without nodes in the match, like
+    /// optional ones, satisfy every predicate.
+    pub fn satisfied(&self, m: &QueryMatch, code: &str) -> bool {
+        self.predicates[m.pattern_index].iter().all(|predicate| {
+            let mut nodes = m
+                .nodes_for_capture_index(predicate.capture_index)
+                .peekable();
+            if nodes.peek().is_none() {
+                return true;
+            }
+            let mut matches =
+                nodes.map(|node| predicate.check.matches(node, code) != predicate.negate);
+            if predicate.any {
+                matches.any(|matches| matches)
+            } else {
+                matches.all(|matches| matches)
+            }
+        })
+    }
+
+    /// The byte offset of the row and byte column, where the column is clamped to the end of its line and moved
+    /// back to the start of a multi-byte character, so the offset can always be sliced.
+    fn byte_at(&self, row: usize, column: usize, code: &str) -> usize {
+        let Some(&line_start) = self.line_starts.get(row) else {
+            return code.len();
+        };
+        let line_end = match self.line_starts.get(row + 1) {
+            // without the newline
+            Some(next_line_start) => next_line_start - 1,
+            None => code.len(),
+        };
+        let mut byte = line_start.saturating_add(column).min(line_end);
+        while !code.is_char_boundary(byte) {
+            byte -= 1;
+        }
+        byte
+    }
+
+    /// The byte range of the captured node after applying the `#offset!` directives of the pattern.
+    pub fn capture_range(
+        &self,
+        pattern_index: usize,
+        capture_index: u32,
+        node: Node,
+        code: &str,
+    ) -> Range<usize> {
+        let mut range = node.byte_range();
+        for offset in &self.offsets[pattern_index] {
+            if offset.capture_index != capture_index {
+                continue;
+            }
+            let shift = |(row, column): (usize, usize),
+                         (row_offset, column_offset): (isize, isize)| {
+                self.byte_at(
+                    row.saturating_add_signed(row_offset),
+                    column.saturating_add_signed(column_offset),
+                    code,
+                )
+            };
+            let start = shift(
+                (node.start_position().row, node.start_position().column),
+                offset.start,
+            );
+            let end = shift(
+                (node.end_position().row, node.end_position().column),
+                offset.end,
+            );
+            range = start..end.max(start);
+        }
+        range
+    }
+}
+
+/// The `priority` of `#set! priority 105` or `#set! @capture priority 105` for the capture.
+pub fn capture_priority(query: &Query, pattern_index: usize, capture_index: u32) -> usize {
+    query
+        .property_settings(pattern_index)
+        .iter()
+        .filter(|property| {
+            property.key.as_ref() == "priority"
+                && property
+                    .capture_id
+                    .is_none_or(|capture_id| capture_id == capture_index as usize)
+        })
+        .filter_map(|property| property.value.as_ref()?.parse().ok())
+        .next_back()
+        .unwrap_or(DEFAULT_PRIORITY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lua_pattern_to_regex;
+    use crate::languages::tests::run_with_args;
+    use regex::Regex;
+
+    fn run_query(code: &str, highlights: &str, extra_args: Vec<&str>) -> String {
+        run_with_args(
+            code,
+            "rust",
+            [vec!["--highlights", highlights], extra_args].concat(),
+        )
+    }
+
+    #[test]
+    fn test_general_predicates_and_offset() {
+        let code =
+            "const MAX: i32 = 1; // note\nfn f(x: S) { x.y; let s = \"ab\"; let t = \"cd\"; }";
+        let highlights = r#"
+((identifier) @constant (#lua-match? @constant "^%u[%u%d_]*$"))
+((identifier) @variable
+  (#not-has-parent? @variable parameter)
+  (#not-kind-eq? @variable type_identifier)
+  (#not-lua-match? @variable "^%u"))
+((field_identifier) @property (#has-ancestor? @property function_item))
+((string_literal) @string (#contains? @string "a" "z"))
+((line_comment) @comment (#offset! @comment 0 3 0 0))
+((identifier) @parameter (#has-parent? @parameter parameter))
+"#;
+        assert_eq!(
+            run_query(code, highlights, vec!["--capture-text"]),
+            r#"constant 6 9 text:"MAX"
+comment 23 27 text:"note"
+variable 31 32 text:"f"
+parameter 33 34 text:"x"
+variable 41 42 text:"x"
+property 43 44 text:"y"
+variable 50 51 text:"s"
+string 54 58 text:"\"ab\""
+variable 64 65 text:"t"
+"#
+        );
+    }
+
+    #[test]
+    fn test_offset_is_clamped() {
+        // the end column is past the line end and the start column is inside of é, which are both clamped
+        let highlights = r#"((string_literal) @string (#offset! @string 0 2 0 5))"#;
+        assert_eq!(
+            run_query("f(\"é\");\ng();", highlights, vec!["--capture-text"]),
+            "string 3 8 text:\"é\\\");\"\n"
+        );
+        // a 2 byte node, where the end column would be in the next line without the clamping
+        assert_eq!(
+            run_with_args(
+                "é\nx",
+                "python",
+                vec![
+                    "--highlights",
+                    "((identifier) @x (#offset! @x 0 0 0 5))",
+                    "--capture-text"
+                ]
+            ),
+            "x 0 2 text:\"é\"\nx 3 4 text:\"x\"\n"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_predicate_is_ignored() {
+        let highlights = r#"((identifier) @function (#lua-match? @function "%b()"))
+((identifier) @variable (#lua-match? @variable "^x"))"#;
+        assert_eq!(
+            run_query("x(y)", highlights, vec![]),
+            "function 0 1\nvariable 0 1\nfunction 2 3\n"
+        );
+    }
+
+    #[test]
+    fn test_priority() {
+        // without a priority, the first pattern and then the innermost capture win
+        let highlights =
+            r#"(identifier) @variable ((identifier) @function) (call_expression) @call"#;
+        assert_eq!(
+            run_query("f()", highlights, vec!["--format", "html"]),
+            "<pre class=\"hl\"><span class=\"hl-variable\">f</span><span class=\"hl-call\">()</span></pre>\n"
+        );
+        let highlights = r#"(identifier) @variable
+((identifier) @function (#set! priority 110))
+((call_expression) @call (#set! @call priority 120))"#;
+        assert_eq!(
+            run_query("f()", highlights, vec!["--format", "html"]),
+            "<pre class=\"hl\"><span class=\"hl-call\">f()</span></pre>\n"
+        );
+        let highlights = r#"(identifier) @variable ((identifier) @function (#set! priority 110))"#;
+        assert_eq!(
+            run_query("f()", highlights, vec!["--format", "html"]),
+            "<pre class=\"hl\"><span class=\"hl-function\">f</span>()</pre>\n"
+        );
+    }
+
+    #[test]
+    fn test_lua_patterns() {
+        let matches = |pattern: &str, text: &str| {
+            Regex::new(&lua_pattern_to_regex(pattern).expect("Pattern should be supported"))
+                .expect("Regex should be valid")
+                .is_match(text)
+        };
+        assert!(matches("^%u[%w_]*$", "Foo_1"));
+        assert!(!matches("^%u[%w_]*$", "foo"));
+        assert!(matches("^[A-Z][A-Z%d_]+$", "MAX_2"));
+        assert!(matches("^%-%-%-", "--- doc"));
+        assert!(matches("a.-b", "a{x}b"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("^[^%s]+$", "a|b"));
+        assert!(matches("x$y", "x$y"));
+        // an escaped - inside a set is a literal, not a range
+        assert!(matches("^[a%-z]$", "-"));
+        assert!(!matches("^[a%-z]$", "m"));
+        assert!(matches("^[%w%-_]+$", "foo-bar_1"));
+        assert!(!matches("^[%w%-_]+$", "foo.bar"));
+        assert!(matches("^[a%-%-]$", "-"));
+        assert!(lua_pattern_to_regex("%b()").is_err());
+        assert!(lua_pattern_to_regex("[abc").is_err());
+    }
+}
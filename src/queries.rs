@@ -0,0 +1,321 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Where the queries of a language like `highlights.scm` are looked up.
+#[derive(Clone, Default)]
+pub struct QueryPaths {
+    /// The `[queries]` of `--config`, which map a language to its directory of query files.
+    pub overrides: HashMap<String, PathBuf>,
+    /// The `--query-dir` roots, with a subdirectory per language.
+    pub query_dirs: Vec<PathBuf>,
+}
+
+/// The query of the grammar crate of the language, which is compiled into the binary, for the query name like
+/// `highlights`. These are all the highlights, tags, injections and locals queries the crates export.
+pub fn builtin_query(language: &str, name: &str) -> Option<&'static str> {
+    let query = match (language, name) {
+        ("kotlin", "highlights") => tree_sitter_kotlin::HIGHLIGHTS_QUERY,
+        ("php", "highlights") => tree_sitter_php::HIGHLIGHTS_QUERY,
+        ("php", "injections") => tree_sitter_php::INJECTIONS_QUERY,
+        ("php", "tags") => tree_sitter_php::TAGS_QUERY,
+        ("bash", "highlights") => tree_sitter_bash::HIGHLIGHT_QUERY,
+        ("json", "highlights") => tree_sitter_json::HIGHLIGHTS_QUERY,
+        ("dockerfile", "highlights") => tree_sitter_dockerfile::HIGHLIGHTS_QUERY,
+        ("python", "highlights") => tree_sitter_python::HIGHLIGHTS_QUERY,
+        ("python", "tags") => tree_sitter_python::TAGS_QUERY,
+        ("java", "highlights") => tree_sitter_java::HIGHLIGHTS_QUERY,
+        ("java", "tags") => tree_sitter_java::TAGS_QUERY,
+        ("rust", "highlights") => tree_sitter_rust::HIGHLIGHTS_QUERY,
+        ("rust", "injections") => tree_sitter_rust::INJECTIONS_QUERY,
+        ("rust", "tags") => tree_sitter_rust::TAGS_QUERY,
+        ("lua", "highlights") => tree_sitter_lua::HIGHLIGHTS_QUERY,
+        ("toml", "highlights") => tree_sitter_toml::HIGHLIGHT_QUERY,
+        ("groovy", "highlights") => tree_sitter_groovy::HIGHLIGHTS_QUERY,
+        ("css", "highlights") => tree_sitter_css::HIGHLIGHTS_QUERY,
+        ("html", "highlights") => tree_sitter_html::HIGHLIGHTS_QUERY,
+        ("html", "injections") => tree_sitter_html::INJECTIONS_QUERY,
+        ("javascript", "highlights") => tree_sitter_javascript::HIGHLIGHT_QUERY,
+        ("javascript", "injections") => tree_sitter_javascript::INJECTIONS_QUERY,
+        ("javascript", "locals") => tree_sitter_javascript::LOCALS_QUERY,
+        ("javascript", "tags") => tree_sitter_javascript::TAGS_QUERY,
+        ("markdown", "highlights") => tree_sitter_md::HIGHLIGHT_QUERY_BLOCK,
+        ("markdown", "injections") => tree_sitter_md::INJECTION_QUERY_BLOCK,
+        ("markdown-inline", "highlights") => tree_sitter_md::HIGHLIGHT_QUERY_INLINE,
+        ("markdown-inline", "injections") => tree_sitter_md::INJECTION_QUERY_INLINE,
+        _ => return None,
+    };
+    Some(query)
+}
+
+impl QueryPaths {
+    /// The paths of the query file for the language in the order they are tried: the `--config` override and
+    /// `<query dir>/<language>/<file name>`.
+    fn candidates(&self, language: &str, file_name: &str) -> Vec<PathBuf> {
+        let mut candidates: Vec<PathBuf> = self
+            .overrides
+            .get(language)
+            .map(|dir| dir.join(file_name))
+            .into_iter()
+            .collect();
+        candidates.extend(
+            self.query_dirs
+                .iter()
+                .map(|dir| dir.join(language).join(file_name)),
+        );
+        candidates
+    }
+
+    /// The first existing query file like `highlights.scm` for the language.
+    pub fn find(&self, language: &str, file_name: &str) -> Option<PathBuf> {
+        self.candidates(language, file_name)
+            .into_iter()
+            .find(|path| path.is_file())
+    }
+}
+
+/// The languages of the `; inherits: ecma,(jsx)` lines at the start of the query. Like in nvim-treesitter,
+/// languages in parentheses are only inherited by the query which was passed, not by inherited queries.
+fn inherited_languages(query: &str, top_level: bool) -> Vec<String> {
+    let mut languages = Vec::new();
+    for line in query.lines() {
+        let Some(comment) = line.trim().strip_prefix(';') else {
+            break;
+        };
+        let Some(names) = comment
+            .trim_start_matches(';')
+            .trim()
+            .strip_prefix("inherits:")
+        else {
+            continue;
+        };
+        for name in names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            match name
+                .strip_prefix('(')
+                .and_then(|name| name.strip_suffix(')'))
+            {
+                Some(optional) if top_level => languages.push(optional.to_string()),
+                Some(_) => {}
+                None => languages.push(name.to_string()),
+            }
+        }
+    }
+    languages
+}
+
+struct Resolver<'a> {
+    /// The file name of the query like `highlights.scm`, which is looked up for inherited languages.
+    file_name: &'a str,
+    query_paths: &'a QueryPaths,
+    /// The query files which were already included, so a query inherited twice is only included once.
+    included: HashSet<PathBuf>,
+    /// The languages currently being resolved, for the cycle detection.
+    stack: Vec<String>,
+}
+
+impl Resolver<'_> {
+    fn find(&self, language: &str) -> Result<PathBuf, String> {
+        self.query_paths
+            .find(language, self.file_name)
+            .ok_or_else(|| {
+                let candidates: Vec<String> = self
+                    .query_paths
+                    .candidates(language, self.file_name)
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                format!(
+                    "{} of the inherited language '{}' was not found, tried: {}",
+                    self.file_name,
+                    language,
+                    candidates.join(", ")
+                )
+            })
+    }
+
+    fn resolve(&mut self, query: &str, top_level: bool) -> Result<String, String> {
+        let mut output = String::new();
+        for language in inherited_languages(query, top_level) {
+            if self.stack.contains(&language) {
+                return Err(format!(
+                    "cyclic inherits: {} -> {}",
+                    self.stack.join(" -> "),
+                    language
+                ));
+            }
+            let path = self.find(&language)?;
+            if !self.included.insert(path.clone()) {
+                continue;
+            }
+            let inherited = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Error reading '{}': {}", path.display(), e))?;
+            self.stack.push(language);
+            output.push_str(&self.resolve(&inherited, false)?);
+            self.stack.pop();
+            output.push('\n');
+        }
+        output.push_str(query);
+        Ok(output)
+    }
+}
+
+/// Prepends the queries of the languages in the `; inherits:` lines of the query recursively, like
+/// nvim-treesitter does.
+///
+/// The inherited queries are looked up like other queries of a language in `query_paths`, where the directory
+/// next to the language directory of `file_path` is an additional query dir, so
+/// `queries/typescript/highlights.scm` finds `queries/ecma/highlights.scm`. `name` is the query name like
+/// `highlights` for queries which were not read from a file.
+pub fn resolve_inherits(
+    query: &str,
+    name: &str,
+    file_path: Option<&Path>,
+    query_paths: &QueryPaths,
+) -> Result<String, String> {
+    let file_name = match file_path.and_then(Path::file_name) {
+        Some(file_name) => file_name.to_string_lossy().to_string(),
+        None => format!("{}.scm", name),
+    };
+    let language_dir = file_path.and_then(Path::parent);
+    let mut query_paths = query_paths.clone();
+    query_paths
+        .query_dirs
+        .extend(language_dir.and_then(Path::parent).map(Path::to_path_buf));
+    let mut resolver = Resolver {
+        file_name: &file_name,
+        query_paths: &query_paths,
+        included: file_path.map(Path::to_path_buf).into_iter().collect(),
+        stack: language_dir
+            .and_then(Path::file_name)
+            .map(|language| language.to_string_lossy().to_string())
+            .into_iter()
+            .collect(),
+    };
+    resolver.resolve(query, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args;
+    use std::path::{Path, PathBuf};
+
+    /// Creates `<temp dir>/<test name>/<language>/highlights.scm` for every query.
+    fn write_queries(test_name: &str, queries: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("tree-sitter-cli-via-rust-{}", test_name));
+        for (language, query) in queries {
+            let dir = root.join(language);
+            std::fs::create_dir_all(&dir).expect("Query directory should be creatable");
+            std::fs::write(dir.join("highlights.scm"), query).expect("Query should be writable");
+        }
+        root
+    }
+
+    fn run_highlights_file(path: &Path, extra_args: Vec<&str>) -> String {
+        let args = [
+            vec!["--highlights-file", path.to_str().unwrap()],
+            extra_args,
+        ]
+        .concat();
+        run_with_args("let x = <a/>;", "javascript", args)
+    }
+
+    #[test]
+    fn test_inherits() {
+        let root = write_queries(
+            "inherits",
+            &[
+                ("ecma", "\"let\" @keyword"),
+                ("jsx", "; inherits: ecma\n(jsx_self_closing_element) @tag"),
+                // the optional (jsx) is only inherited when typescript is the passed query
+                (
+                    "typescript",
+                    "; inherits: ecma,(jsx)\n(identifier) @variable",
+                ),
+                ("tsx", ";; inherits: typescript\n(number) @number"),
+            ],
+        );
+        let extra_dir = write_queries("inherits-extra", &[("ecma", "\"=\" @operator")]);
+        assert_eq!(
+            run_highlights_file(&root.join("typescript/highlights.scm"), vec![]),
+            "keyword 0 3\nvariable 4 5\ntag 8 12\nvariable 9 10\n"
+        );
+        assert_eq!(
+            run_highlights_file(&root.join("tsx/highlights.scm"), vec![]),
+            "keyword 0 3\nvariable 4 5\nvariable 9 10\n"
+        );
+        // --query-dir is searched first
+        assert_eq!(
+            run_highlights_file(
+                &root.join("typescript/highlights.scm"),
+                vec!["--query-dir", extra_dir.to_str().unwrap()]
+            ),
+            "variable 4 5\noperator 6 7\ntag 8 12\nvariable 9 10\n"
+        );
+        std::fs::remove_dir_all(root).expect("Queries should be removable");
+        std::fs::remove_dir_all(extra_dir).expect("Queries should be removable");
+    }
+
+    #[test]
+    fn test_inherits_cycle() {
+        let root = write_queries(
+            "inherits-cycle",
+            &[
+                ("a", "; inherits: b\n(identifier) @a"),
+                ("b", "; inherits: c\n(identifier) @b"),
+                ("c", "; inherits: a\n(identifier) @c"),
+            ],
+        );
+        let path = root.join("a/highlights.scm");
+        let query = std::fs::read_to_string(&path).expect("Query should be readable");
+        let query_paths = super::QueryPaths::default();
+        let error = super::resolve_inherits(&query, "highlights", Some(&path), &query_paths)
+            .expect_err("Cyclic inherits should fail");
+        assert_eq!(error, "cyclic inherits: a -> b -> c -> a");
+        let error =
+            super::resolve_inherits("; inherits: missing", "highlights", None, &query_paths)
+                .expect_err("Missing languages should fail");
+        assert!(error.starts_with("highlights.scm of the inherited language 'missing'"));
+        std::fs::remove_dir_all(root).expect("Queries should be removable");
+    }
+
+    #[test]
+    fn test_query_lookup_by_language() {
+        let root = write_queries(
+            "lookup",
+            &[
+                ("javascript", "; inherits: ecma\n(identifier) @variable"),
+                ("ecma", "\"let\" @keyword"),
+                ("override", "(identifier) @override"),
+            ],
+        );
+        let run = |extra_args: Vec<&str>| {
+            let args = [vec!["--query-dir", root.to_str().unwrap()], extra_args].concat();
+            run_with_args("let x", "javascript", args)
+        };
+        assert_eq!(run(vec![]), "keyword 0 3\nvariable 4 5\n");
+        assert_eq!(run(vec!["--highlights", "\"let\" @let"]), "let 0 3\n");
+
+        // the config override is relative to the config file and wins over --query-dir
+        let config_path = root.join("config.toml");
+        std::fs::write(&config_path, "[queries]\njavascript = \"override\"")
+            .expect("Config file should be writable");
+        assert_eq!(
+            run(vec!["--config", config_path.to_str().unwrap()]),
+            "override 4 5\n"
+        );
+        std::fs::remove_dir_all(root).expect("Queries should be removable");
+
+        // without any query file, the query of the grammar crate is used
+        let output = run_with_args("fn f() {}", "rust", vec!["--query-dir", "does-not-exist"]);
+        assert!(output.starts_with("keyword 0 2\n"));
+        assert_eq!(super::builtin_query("rust", "locals"), None);
+
+        // the tags of the Java crate for --mode symbols
+        let output = run_with_args("class A { void f() {} }", "java", vec!["--mode", "symbols"]);
+        assert!(output.contains("\"kind\":\"method\",\"name\":\"f\""));
+        assert!(output.contains("\"kind\":\"class\",\"name\":\"A\""));
+    }
+}
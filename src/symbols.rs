@@ -0,0 +1,251 @@
+use crate::json::range_to_json;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::exit;
+use tree_sitter::{
+    Node, Parser, Query, QueryCursor, QueryMatch, QueryPredicateArg, Range, StreamingIterator, Tree,
+};
+
+/// One `@definition.*` capture from a tags.scm query.
+struct Symbol {
+    name: String,
+    /// The definition capture name without the `definition.` prefix, like `class` or `function`.
+    kind: String,
+    range: Range,
+    name_range: Range,
+    docs: Option<String>,
+    pattern_index: usize,
+    children: Vec<Symbol>,
+}
+
+impl Symbol {
+    fn contains(&self, other: &Symbol) -> bool {
+        self.range.start_byte <= other.range.start_byte
+            && other.range.end_byte <= self.range.end_byte
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "kind": self.kind,
+            "range": range_to_json(self.range),
+            "name_range": range_to_json(self.name_range),
+            "docs": self.docs,
+            "children": self.children.iter().map(Symbol::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Collects the `@doc` captures of a match and applies the `#select-adjacent!` and `#strip!` directives the
+/// same way tree-sitter-tags does.
+fn collect_docs(query: &Query, m: &QueryMatch, code: &str, doc_index: u32) -> Option<String> {
+    let mut doc_nodes: Vec<Node> = m.nodes_for_capture_index(doc_index).collect();
+    if doc_nodes.is_empty() {
+        return None;
+    }
+    let mut strip_regex = None;
+    for predicate in query.general_predicates(m.pattern_index) {
+        match (predicate.operator.as_ref(), predicate.args.as_ref()) {
+            ("strip!", [QueryPredicateArg::Capture(_), QueryPredicateArg::String(pattern)]) => {
+                strip_regex = Some(Regex::new(&format!("(?m){}", pattern)).unwrap_or_else(|e| {
+                    eprintln!("Invalid #strip! regex '{}': {}", pattern, e);
+                    exit(1);
+                }));
+            }
+            (
+                "select-adjacent!",
+                [QueryPredicateArg::Capture(_), QueryPredicateArg::Capture(adjacent_index)],
+            ) => {
+                if let Some(adjacent) = m.nodes_for_capture_index(*adjacent_index).next() {
+                    // walk backwards from the definition and keep only the docs without a blank line in between
+                    let mut next_row = adjacent.start_position().row;
+                    let mut adjacent_docs = Vec::new();
+                    for doc in doc_nodes.iter().rev() {
+                        if doc.end_position().row + 1 < next_row {
+                            break;
+                        }
+                        next_row = doc.start_position().row;
+                        adjacent_docs.push(*doc);
+                    }
+                    adjacent_docs.reverse();
+                    doc_nodes = adjacent_docs;
+                }
+            }
+            _ => {}
+        }
+    }
+    let docs: Vec<String> = doc_nodes
+        .iter()
+        .map(|doc| {
+            let text = &code[doc.byte_range()];
+            match &strip_regex {
+                Some(regex) => regex.replace_all(text, "").to_string(),
+                None => text.to_string(),
+            }
+        })
+        .collect();
+    if docs.is_empty() {
+        None
+    } else {
+        Some(docs.join("\n"))
+    }
+}
+
+/// Symbols are sorted by start ascending and end descending, so a symbol is either nested in the last
+/// inserted one or starts a new sibling.
+fn insert_symbol(symbols: &mut Vec<Symbol>, symbol: Symbol) {
+    if let Some(last) = symbols.last_mut() {
+        if last.contains(&symbol) {
+            insert_symbol(&mut last.children, symbol);
+            return;
+        }
+    }
+    symbols.push(symbol);
+}
+
+/// Outputs a hierarchical JSON array of the `@definition.*` captures of a tags.scm query.
+///
+/// Each symbol has `name`, `kind`, `range`, `name_range` (the range of the `@name` capture, which is
+/// `selectionRange` in LSP), `docs` and `children`, which maps directly to an Emacs imenu index or LSP
+/// DocumentSymbol.
+pub fn process_symbols<W>(parser: Parser, tags: &str, tree: &Tree, code: &String, writer: &mut W)
+where
+    W: Write,
+{
+    let parser_language = parser.language().unwrap();
+    let query = match Query::new(&parser_language, tags) {
+        Ok(query) => query,
+        Err(_) => {
+            eprintln!("Failed to create query for passed tags");
+            exit(1);
+        }
+    };
+    let Some(name_index) = query.capture_index_for_name("name") else {
+        eprintln!("The tags query needs a @name capture");
+        exit(1);
+    };
+    let doc_index = query.capture_index_for_name("doc");
+
+    // the same definition node can be matched by multiple patterns, like a Rust method which is also a
+    // function, so the earliest pattern wins as in tree-sitter-tags
+    let mut symbols_by_node: HashMap<usize, Symbol> = HashMap::new();
+    let mut query_cursor = QueryCursor::new();
+    let mut matches = query_cursor.matches(&query, tree.root_node(), code.as_bytes());
+    while let Some(m) = matches.next() {
+        let Some((definition, kind)) = m.captures.iter().find_map(|capture| {
+            query.capture_names()[capture.index as usize]
+                .strip_prefix("definition.")
+                .map(|kind| (capture.node, kind))
+        }) else {
+            continue;
+        };
+        let Some(name_node) = m.nodes_for_capture_index(name_index).next() else {
+            continue;
+        };
+        if let Some(existing) = symbols_by_node.get(&definition.id()) {
+            if existing.pattern_index <= m.pattern_index {
+                continue;
+            }
+        }
+        symbols_by_node.insert(
+            definition.id(),
+            Symbol {
+                name: code[name_node.byte_range()].to_string(),
+                kind: kind.to_string(),
+                range: definition.range(),
+                name_range: name_node.range(),
+                docs: doc_index.and_then(|doc_index| collect_docs(&query, m, code, doc_index)),
+                pattern_index: m.pattern_index,
+                children: Vec::new(),
+            },
+        );
+    }
+
+    let mut sorted: Vec<Symbol> = symbols_by_node.into_values().collect();
+    sorted.sort_by(|a, b| {
+        a.range
+            .start_byte
+            .cmp(&b.range.start_byte)
+            .then(b.range.end_byte.cmp(&a.range.end_byte))
+    });
+    let mut symbols = Vec::new();
+    for symbol in sorted {
+        insert_symbol(&mut symbols, symbol);
+    }
+    let output = Value::Array(symbols.iter().map(Symbol::to_json).collect());
+    writeln!(writer, "{}", output).expect("write should succeed");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args;
+    use serde_json::Value;
+
+    fn run_symbols(code: &str, language: &str, tags: &str) -> Value {
+        let output = run_with_args(code, language, vec!["--mode", "symbols", "--tags", tags]);
+        serde_json::from_str(&output).expect("Output should be valid JSON")
+    }
+
+    #[test]
+    fn test_symbols_rust_hierarchy() {
+        let symbols = run_symbols(
+            "struct Point {}\nimpl Point {\n    fn new() {}\n}\nmod util {\n    fn helper() {}\n}",
+            "rust",
+            tree_sitter_rust::TAGS_QUERY,
+        );
+        let symbols = symbols.as_array().unwrap();
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols[0]["name"], "Point");
+        assert_eq!(symbols[0]["kind"], "class");
+        assert_eq!(symbols[0]["range"]["start"], 0);
+        assert_eq!(symbols[0]["range"]["end"], 15);
+        assert_eq!(symbols[0]["name_range"]["start"], 7);
+        assert_eq!(symbols[0]["name_range"]["end"], 12);
+        // the method pattern comes before the function pattern in tags.scm
+        assert_eq!(symbols[1]["name"], "new");
+        assert_eq!(symbols[1]["kind"], "method");
+        assert_eq!(symbols[1]["range"]["start_point"]["row"], 2);
+        assert_eq!(symbols[2]["name"], "util");
+        assert_eq!(symbols[2]["kind"], "module");
+        let children = symbols[2]["children"].as_array().unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0]["name"], "helper");
+        // module bodies are also declaration_list nodes, so tags.scm reports this as method
+        assert_eq!(children[0]["kind"], "method");
+    }
+
+    #[test]
+    fn test_symbols_python_nested_methods() {
+        let symbols = run_symbols(
+            "class A:\n    def f(self):\n        pass\n\ndef g():\n    pass\n",
+            "python",
+            tree_sitter_python::TAGS_QUERY,
+        );
+        assert_eq!(symbols[0]["name"], "A");
+        assert_eq!(symbols[0]["kind"], "class");
+        assert_eq!(symbols[0]["children"][0]["name"], "f");
+        assert_eq!(symbols[0]["children"][0]["kind"], "function");
+        assert_eq!(symbols[1]["name"], "g");
+        assert_eq!(symbols[1]["children"].as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_symbols_docs() {
+        let tags = r#"
+((line_comment)* @doc
+ .
+ (function_item name: (identifier) @name) @definition.function
+ (#strip! @doc "^//\\s*")
+ (#select-adjacent! @doc @definition.function))
+"#;
+        let symbols = run_symbols(
+            "// unrelated\n\n// first line\n// second line\nfn documented() {}",
+            "rust",
+            tags,
+        );
+        assert_eq!(symbols[0]["name"], "documented");
+        assert_eq!(symbols[0]["docs"], "first line\nsecond line");
+    }
+}
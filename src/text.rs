@@ -0,0 +1,43 @@
+/// Truncates to `max_chars` characters and appends `...` when something was cut off.
+///
+/// This counts characters instead of bytes, so it never splits a multi-byte character like an emoji.
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((byte_index, _)) => format!("{}...", &text[..byte_index]),
+        None => text.to_string(),
+    }
+}
+
+/// Escapes text for HTML element content and double quoted attribute values.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Matches a glob pattern like `keyword.*` against the whole text, where `*` matches any characters and `?`
+/// matches a single character.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // the position after the last `*` and the text position it currently matches up to, for backtracking
+    let mut star = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            p += 1;
+            star = Some((p, t));
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
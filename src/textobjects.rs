@@ -0,0 +1,250 @@
+use std::io::Write;
+use std::ops::Range;
+use std::process::exit;
+use tree_sitter::{Parser, Query, QueryCursor, QueryPredicateArg, StreamingIterator, Tree};
+
+pub static DIRECTIONS: [&str; 3] = ["current", "next", "previous"];
+
+/// Collects the byte ranges of all captures named `object`.
+///
+/// Quantified captures like `(comment)+ @comment.outer` are merged into one range per match and the
+/// `#make-range!` directive of nvim-treesitter-textobjects is supported for ranges spanning two captures.
+fn collect_object_ranges(
+    query: &Query,
+    object: &str,
+    tree: &Tree,
+    code: &String,
+) -> Vec<Range<usize>> {
+    let object_index = query.capture_index_for_name(object);
+    let mut ranges = Vec::new();
+    let mut query_cursor = QueryCursor::new();
+    let mut matches = query_cursor.matches(query, tree.root_node(), code.as_bytes());
+    while let Some(m) = matches.next() {
+        for predicate in query.general_predicates(m.pattern_index) {
+            if let (
+                "make-range!",
+                [QueryPredicateArg::String(name), QueryPredicateArg::Capture(start_index), QueryPredicateArg::Capture(end_index)],
+            ) = (predicate.operator.as_ref(), predicate.args.as_ref())
+            {
+                if name.as_ref() != object {
+                    continue;
+                }
+                let start = m.nodes_for_capture_index(*start_index).next();
+                let end = m.nodes_for_capture_index(*end_index).last();
+                if let (Some(start), Some(end)) = (start, end) {
+                    ranges.push(start.start_byte()..end.end_byte());
+                }
+            }
+        }
+        if let Some(object_index) = object_index {
+            let merged = m
+                .nodes_for_capture_index(object_index)
+                .map(|node| node.byte_range())
+                .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end));
+            if let Some(range) = merged {
+                ranges.push(range);
+            }
+        }
+    }
+    ranges.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+    ranges.dedup();
+    ranges
+}
+
+/// The smallest range which contains the position.
+fn find_current(ranges: &[Range<usize>], position: usize) -> Option<Range<usize>> {
+    ranges
+        .iter()
+        .filter(|range| range.start <= position && position < range.end)
+        .min_by_key(|range| range.len())
+        .cloned()
+}
+
+/// The outermost range starting after `position`, so nested objects of the returned range are skipped.
+fn find_next(ranges: &[Range<usize>], position: usize) -> Option<Range<usize>> {
+    ranges
+        .iter()
+        .filter(|range| range.start >= position)
+        .min_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)))
+        .cloned()
+}
+
+/// The outermost range ending before `position`, so nested objects of the returned range are skipped.
+fn find_previous(ranges: &[Range<usize>], position: usize) -> Option<Range<usize>> {
+    ranges
+        .iter()
+        .filter(|range| range.end <= position)
+        .max_by(|a, b| a.end.cmp(&b.end).then(b.start.cmp(&a.start)))
+        .cloned()
+}
+
+/// Outputs `{object} {byteRangeStart} {byteRangeEnd}` for the text object around the position, or for the
+/// `count`th next or previous one. Nothing is output when there is no such text object.
+///
+/// Navigation starts from the boundaries of the current text object, if there is one, so the objects nested
+/// inside it are skipped.
+#[allow(clippy::too_many_arguments)]
+pub fn process_textobject<W>(
+    parser: Parser,
+    textobjects: &str,
+    object: &str,
+    position: usize,
+    direction: &str,
+    count: usize,
+    tree: &Tree,
+    code: &String,
+    writer: &mut W,
+) where
+    W: Write,
+{
+    let parser_language = parser.language().unwrap();
+    let query = match Query::new(&parser_language, textobjects) {
+        Ok(query) => query,
+        Err(_) => {
+            eprintln!("Failed to create query for passed textobjects");
+            exit(1);
+        }
+    };
+    let ranges = collect_object_ranges(&query, object, tree, code);
+    let current = find_current(&ranges, position);
+    let result = match direction {
+        "next" => {
+            let mut found = None;
+            let mut position = current.map_or(position, |range| range.end);
+            for _ in 0..count {
+                found = find_next(&ranges, position);
+                match &found {
+                    Some(range) => position = range.end,
+                    None => break,
+                }
+            }
+            found
+        }
+        "previous" => {
+            let mut found = None;
+            let mut position = current.map_or(position, |range| range.start);
+            for _ in 0..count {
+                found = find_previous(&ranges, position);
+                match &found {
+                    Some(range) => position = range.start,
+                    None => break,
+                }
+            }
+            found
+        }
+        _ => current,
+    };
+    if let Some(range) = result {
+        writeln!(writer, "{} {} {}", object, range.start, range.end).expect("write should succeed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args;
+
+    const TEXTOBJECTS: &str = r#"
+(function_item) @function.outer
+(function_item body: (block . "{" . (_) @_start @_end (_)? @_end . "}"
+  (#make-range! "function.inner" @_start @_end)))
+(impl_item) @class.outer
+(parameter) @parameter.inner
+(line_comment)+ @comment.outer
+"#;
+
+    const CODE: &str = r#"// one
+// two
+fn a(x: i32, y: i32) {
+    let z = 1;
+    z
+}
+
+impl A {
+    fn b() {}
+}
+
+fn c() {}
+"#;
+
+    fn run_textobject(object: &str, position: usize, direction: &str, count: usize) -> String {
+        let position = position.to_string();
+        let count = count.to_string();
+        let args = vec![
+            "--mode",
+            "textobject",
+            "--textobjects",
+            TEXTOBJECTS,
+            "--object",
+            object,
+            "--position",
+            &position,
+            "--direction",
+            direction,
+            "--count",
+            &count,
+        ];
+        run_with_args(CODE, "rust", args)
+    }
+
+    #[test]
+    fn test_textobject_current() {
+        // inside "let z = 1;"
+        assert_eq!(
+            run_textobject("function.outer", 45, "current", 1),
+            "function.outer 14 59\n"
+        );
+        assert_eq!(
+            run_textobject("function.inner", 45, "current", 1),
+            "function.inner 41 57\n"
+        );
+        assert_eq!(
+            run_textobject("parameter.inner", 19, "current", 1),
+            "parameter.inner 19 25\n"
+        );
+        // both line comments are merged into one range
+        assert_eq!(
+            run_textobject("comment.outer", 8, "current", 1),
+            "comment.outer 0 13\n"
+        );
+        // the smallest function wins for nested text objects
+        assert_eq!(
+            run_textobject("function.outer", 78, "current", 1),
+            "function.outer 74 83\n"
+        );
+        assert_eq!(run_textobject("class.outer", 0, "current", 1), "");
+    }
+
+    #[test]
+    fn test_textobject_navigation() {
+        assert_eq!(
+            run_textobject("function.outer", 0, "next", 1),
+            "function.outer 14 59\n"
+        );
+        assert_eq!(
+            run_textobject("function.outer", 45, "next", 1),
+            "function.outer 74 83\n"
+        );
+        assert_eq!(
+            run_textobject("function.outer", 45, "next", 2),
+            "function.outer 87 96\n"
+        );
+        assert_eq!(run_textobject("function.outer", 45, "next", 3), "");
+        assert_eq!(
+            run_textobject("class.outer", 90, "previous", 1),
+            "class.outer 61 85\n"
+        );
+        assert_eq!(
+            run_textobject("function.outer", 90, "previous", 1),
+            "function.outer 74 83\n"
+        );
+        assert_eq!(
+            run_textobject("function.outer", 90, "previous", 2),
+            "function.outer 14 59\n"
+        );
+        // fn b in the impl at the position starts after it, so the previous function is fn a
+        assert_eq!(
+            run_textobject("function.outer", 61, "previous", 1),
+            "function.outer 14 59\n"
+        );
+    }
+}
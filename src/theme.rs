@@ -0,0 +1,293 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+pub static COLOR_DEPTHS: [&str; 3] = ["16", "256", "truecolor"];
+
+/// Used when no `--theme` is passed, in the same format as the theme file.
+pub const DEFAULT_THEME: &str = r##"{
+  "attribute": "#e5c07b",
+  "comment": {"color": "#7f848e", "italic": true},
+  "constant": "#d19a66",
+  "constant.builtin": "#d19a66",
+  "constructor": "#e5c07b",
+  "escape": "#56b6c2",
+  "function": "#61afef",
+  "function.builtin": "#56b6c2",
+  "keyword": "#c678dd",
+  "label": "#e06c75",
+  "module": "#e5c07b",
+  "number": "#d19a66",
+  "operator": "#56b6c2",
+  "property": "#e06c75",
+  "punctuation": "#abb2bf",
+  "string": "#98c379",
+  "string.special": "#56b6c2",
+  "tag": "#e06c75",
+  "type": "#e5c07b",
+  "type.builtin": "#e5c07b",
+  "variable.builtin": "#e06c75",
+  "variable.parameter": "#e06c75"
+}"##;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    /// An index into the 256 color palette of the terminal, where 0 to 15 are the 16 basic colors.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    pub color: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+/// Maps capture names to styles, in the theme format of the tree-sitter CLI: each value is a palette index
+/// like `56`, a hex color like `"#c678dd"`, a basic color name like `"red"` or an object with `color`, `bold`,
+/// `italic` and `underline`.
+#[derive(Clone)]
+pub struct Theme {
+    styles: HashMap<String, Style>,
+}
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+fn parse_color(value: &Value) -> Result<Color, String> {
+    match value {
+        Value::Number(number) => number
+            .as_u64()
+            .filter(|&index| index < 256)
+            .map(|index| Color::Indexed(index as u8))
+            .ok_or_else(|| format!("'{}' is not a color index from 0 to 255", number)),
+        Value::String(string) => {
+            if let Some(hex) = string.strip_prefix('#') {
+                let channel = |index: usize| {
+                    hex.get(index..index + 2)
+                        .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+                };
+                match (hex.len(), channel(0), channel(2), channel(4)) {
+                    (6, Some(r), Some(g), Some(b)) => Ok(Color::Rgb(r, g, b)),
+                    _ => Err(format!("'{}' is not a hex color like #c678dd", string)),
+                }
+            } else {
+                let (bright, name) = match string.strip_prefix("bright-") {
+                    Some(name) => (8, name),
+                    None => (0, string.as_str()),
+                };
+                COLOR_NAMES
+                    .iter()
+                    .position(|color_name| *color_name == name)
+                    .map(|index| Color::Indexed(index as u8 + bright))
+                    .ok_or_else(|| {
+                        format!("'{}' is not a color name like red or bright-red", string)
+                    })
+            }
+        }
+        _ => Err(format!("'{}' is not a color", value)),
+    }
+}
+
+fn parse_style(value: &Value) -> Result<Style, String> {
+    let Value::Object(object) = value else {
+        return Ok(Style {
+            color: Some(parse_color(value)?),
+            ..Style::default()
+        });
+    };
+    let flag = |name: &str| object.get(name).and_then(Value::as_bool).unwrap_or(false);
+    Ok(Style {
+        color: object.get("color").map(parse_color).transpose()?,
+        bold: flag("bold"),
+        italic: flag("italic"),
+        underline: flag("underline"),
+    })
+}
+
+impl Theme {
+    pub fn parse(json: &str) -> Result<Theme, String> {
+        Theme::from_json(serde_json::from_str(json).map_err(|e| e.to_string())?)
+    }
+
+    pub fn from_json(value: Value) -> Result<Theme, String> {
+        let Value::Object(object) = value else {
+            return Err("the theme needs to be a JSON object of capture names".to_string());
+        };
+        let mut styles = HashMap::new();
+        for (name, value) in object {
+            let style = parse_style(&value).map_err(|e| format!("{}: {}", name, e))?;
+            styles.insert(name, style);
+        }
+        Ok(Theme { styles })
+    }
+
+    /// All capture names with their styles, sorted by name so parents come before their children.
+    pub fn styles(&self) -> Vec<(&str, &Style)> {
+        let mut styles: Vec<(&str, &Style)> = self
+            .styles
+            .iter()
+            .map(|(name, style)| (name.as_str(), style))
+            .collect();
+        styles.sort_by_key(|(name, _)| *name);
+        styles
+    }
+
+    /// The style of the capture name, falling back to its parents like `keyword.function` to `keyword`.
+    pub fn style(&self, capture_name: &str) -> Option<&Style> {
+        let mut name = capture_name;
+        loop {
+            if let Some(style) = self.styles.get(name) {
+                return Some(style);
+            }
+            name = &name[..name.rfind('.')?];
+        }
+    }
+}
+
+/// The RGB value of a 256 color palette index, with the xterm defaults for the 16 basic colors.
+fn indexed_to_rgb(index: u8) -> (u8, u8, u8) {
+    const BASIC: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    match index {
+        0..=15 => BASIC[index as usize],
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    channel(r1, r2) + channel(g1, g2) + channel(b1, b2)
+}
+
+/// The closest palette index from `range` for the color.
+fn closest_index(rgb: (u8, u8, u8), range: std::ops::RangeInclusive<u8>) -> u8 {
+    range
+        .min_by_key(|&index| distance(rgb, indexed_to_rgb(index)))
+        .unwrap()
+}
+
+/// The SGR parameters for the foreground color, approximated for terminals with fewer colors.
+fn color_parameters(color: Color, color_depth: &str) -> String {
+    match (color, color_depth) {
+        (Color::Rgb(r, g, b), "truecolor") => format!("38;2;{};{};{}", r, g, b),
+        (Color::Indexed(index), "truecolor") if index >= 16 => {
+            let (r, g, b) = indexed_to_rgb(index);
+            format!("38;2;{};{};{}", r, g, b)
+        }
+        (Color::Rgb(r, g, b), "256") => format!("38;5;{}", closest_index((r, g, b), 16..=255)),
+        (Color::Indexed(index), "256") if index >= 16 => format!("38;5;{}", index),
+        (color, _) => {
+            let index = match color {
+                Color::Indexed(index) if index < 16 => index,
+                Color::Indexed(index) => closest_index(indexed_to_rgb(index), 0..=15),
+                Color::Rgb(r, g, b) => closest_index((r, g, b), 0..=15),
+            };
+            if index < 8 {
+                format!("{}", 30 + index)
+            } else {
+                format!("{}", 90 + index - 8)
+            }
+        }
+    }
+}
+
+impl Style {
+    /// The ANSI escape sequence which starts this style, or an empty string for an empty style.
+    pub fn ansi_start(&self, color_depth: &str) -> String {
+        let mut parameters = Vec::new();
+        if self.bold {
+            parameters.push("1".to_string());
+        }
+        if self.italic {
+            parameters.push("3".to_string());
+        }
+        if self.underline {
+            parameters.push("4".to_string());
+        }
+        if let Some(color) = self.color {
+            parameters.push(color_parameters(color, color_depth));
+        }
+        if parameters.is_empty() {
+            String::new()
+        } else {
+            format!("\x1b[{}m", parameters.join(";"))
+        }
+    }
+
+    /// The CSS declarations of this style like `color: #c678dd; font-weight: bold`.
+    pub fn css(&self) -> String {
+        let mut declarations = Vec::new();
+        if let Some(color) = self.color {
+            let (r, g, b) = match color {
+                Color::Indexed(index) => indexed_to_rgb(index),
+                Color::Rgb(r, g, b) => (r, g, b),
+            };
+            declarations.push(format!("color: #{:02x}{:02x}{:02x}", r, g, b));
+        }
+        if self.bold {
+            declarations.push("font-weight: bold".to_string());
+        }
+        if self.italic {
+            declarations.push("font-style: italic".to_string());
+        }
+        if self.underline {
+            declarations.push("text-decoration: underline".to_string());
+        }
+        declarations.join("; ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, Style, Theme};
+
+    #[test]
+    fn test_theme_fallback_and_colors() {
+        let theme = Theme::parse(
+            r##"{"keyword": "#ff0000", "keyword.return": {"color": 56, "bold": true}, "string": "bright-green"}"##,
+        )
+        .expect("Theme should be valid");
+        assert_eq!(
+            theme.style("keyword.function").unwrap().color,
+            Some(Color::Rgb(255, 0, 0))
+        );
+        assert!(theme.style("keyword.return.conditional").unwrap().bold);
+        assert_eq!(theme.style("variable"), None);
+
+        let red = theme.style("keyword").unwrap();
+        assert_eq!(red.ansi_start("truecolor"), "\x1b[38;2;255;0;0m");
+        assert_eq!(red.ansi_start("256"), "\x1b[38;5;196m");
+        assert_eq!(red.ansi_start("16"), "\x1b[91m");
+        let green = theme.style("string").unwrap();
+        assert_eq!(green.ansi_start("truecolor"), "\x1b[92m");
+        assert_eq!(Style::default().ansi_start("16"), "");
+        assert!(Theme::parse(r##"{"keyword": "#ff00"}"##).is_err());
+    }
+}
@@ -0,0 +1,485 @@
+use crate::elisp::json_to_elisp;
+use crate::graphviz::{is_output_node, stable_node_ids};
+use crate::json::range_to_json;
+use crate::text::escape_html;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use tree_sitter::{Node, Tree};
+
+/// `mermaid` and `plantuml` are generated next to the graphviz dot graph with its options.
+pub static TREE_FORMATS: [&str; 6] = ["sexp", "json", "elisp", "html", "mermaid", "plantuml"];
+
+#[derive(Default)]
+pub struct TreeOptions {
+    /// Also output anonymous nodes like punctuation, quoted like `("=")`.
+    pub include_anonymous: bool,
+    /// Prefix nodes with their field name in the parent, like `name: (identifier)`.
+    pub fields: bool,
+    /// Append the byte range and the `[row, column] - [row, column]` positions to each node.
+    pub ranges: bool,
+    /// Add the source text of leaf nodes to the JSON and Emacs Lisp output.
+    pub text: bool,
+    /// The capture names of the highlights query by node ID, which are shown next to the nodes in the HTML output.
+    pub captures: Option<HashMap<usize, Vec<String>>>,
+}
+
+fn sexp_node_name(node: Node) -> String {
+    let kind = if node.is_named() {
+        node.kind().to_string()
+    } else {
+        format!(
+            "\"{}\"",
+            node.kind().replace("\\", "\\\\").replace("\"", "\\\"")
+        )
+    };
+    if node.is_missing() {
+        format!("MISSING {}", kind)
+    } else {
+        kind
+    }
+}
+
+fn write_sexp_node(
+    node: Node,
+    field: Option<&str>,
+    depth: usize,
+    options: &TreeOptions,
+    output: &mut String,
+) {
+    if depth > 0 {
+        output.push('\n');
+    }
+    output.push_str(&"  ".repeat(depth));
+    if let Some(field) = field.filter(|_| options.fields) {
+        output.push_str(&format!("{}: ", field));
+    }
+    output.push('(');
+    output.push_str(&sexp_node_name(node));
+    if options.ranges {
+        let start = node.start_position();
+        let end = node.end_position();
+        output.push_str(&format!(
+            " {} {} [{}, {}] - [{}, {}]",
+            node.start_byte(),
+            node.end_byte(),
+            start.row,
+            start.column,
+            end.row,
+            end.column
+        ));
+    }
+    let mut cursor = node.walk();
+    if cursor.goto_first_child() {
+        loop {
+            let child = cursor.node();
+            // Like `tree-sitter parse`, missing tokens are shown even when anonymous nodes are hidden.
+            if is_output_node(child, !options.include_anonymous) {
+                write_sexp_node(child, cursor.field_name(), depth + 1, options, output);
+            }
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+    output.push(')');
+}
+
+/// The tree as indented S-expression with one node per line, like `tree-sitter parse` outputs it.
+pub fn generate_sexp(tree: &Tree, options: &TreeOptions) -> String {
+    let mut output = String::new();
+    write_sexp_node(tree.root_node(), None, 0, options, &mut output);
+    output.push('\n');
+    output
+}
+
+fn node_to_json(
+    node: Node,
+    field: Option<&str>,
+    code: &str,
+    id_map: &HashMap<usize, usize>,
+    options: &TreeOptions,
+) -> Value {
+    let mut children = Vec::new();
+    let mut cursor = node.walk();
+    if cursor.goto_first_child() {
+        loop {
+            let child = cursor.node();
+            if is_output_node(child, !options.include_anonymous) {
+                children.push(node_to_json(
+                    child,
+                    cursor.field_name(),
+                    code,
+                    id_map,
+                    options,
+                ));
+            }
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+    let mut output = json!({
+        "id": id_map[&node.id()],
+        "kind": node.kind(),
+        "kind_id": node.kind_id(),
+        "named": node.is_named(),
+        "field": field,
+        "range": range_to_json(node.range()),
+        "error": node.is_error(),
+        "missing": node.is_missing(),
+        "children": children,
+    });
+    if options.text && node.child_count() == 0 {
+        output["text"] = json!(&code[node.byte_range()]);
+    }
+    output
+}
+
+/// The nested tree as JSON where every node has `id`, `kind`, `kind_id`, `named`, `field`, `range`, `error`,
+/// `missing`, `children` and with `TreeOptions::text` the `text` of leaf nodes.
+///
+/// The `id`s are the same as the `node_` IDs of the graphviz dot graph, also when anonymous nodes are omitted.
+pub fn generate_json(tree: &Tree, code: &str, options: &TreeOptions) -> Value {
+    let root = tree.root_node();
+    let id_map = stable_node_ids(root);
+    node_to_json(root, None, code, &id_map, options)
+}
+
+const HTML_STYLE: &str = r##"body { margin: 0; display: flex; height: 100vh; font: 13px monospace; }
+#source, #tree { flex: 1; overflow: auto; margin: 0; padding: 8px; }
+#source { border-right: 1px solid #ccc; white-space: pre; }
+#tree details { margin-left: 16px; }
+#tree summary { cursor: pointer; }
+#tree .leaf { margin-left: 16px; padding-left: 14px; }
+.anonymous { color: gray; }
+.error { color: red; font-weight: bold; }
+.field { color: #7a3e9d; }
+.range { color: #888; }
+.capture { color: #1a7f37; }
+.active { background: #ffe58f; }
+"##;
+
+const HTML_SCRIPT: &str = r##"const segments = [...document.querySelectorAll("#source span")];
+const nodes = [...document.querySelectorAll("#tree [data-start]")];
+function activate(node) {
+  document.querySelectorAll(".active").forEach(element => element.classList.remove("active"));
+  if (!node) return;
+  node.classList.add("active");
+  const start = +node.dataset.start, end = +node.dataset.end;
+  segments.filter(s => +s.dataset.start >= start && +s.dataset.start < end).forEach(s => s.classList.add("active"));
+}
+nodes.forEach(node => node.addEventListener("mouseover", event => { event.stopPropagation(); activate(node); }));
+segments.forEach(segment => segment.addEventListener("mouseover", () => {
+  const offset = +segment.dataset.start;
+  const node = nodes.filter(n => +n.dataset.start <= offset && offset < +n.dataset.end)
+    .reduce((a, b) => (!a || b.dataset.end - b.dataset.start <= a.dataset.end - a.dataset.start) ? b : a, null);
+  for (let parent = node && node.parentElement; parent; parent = parent.parentElement) {
+    if (parent.tagName === "DETAILS") parent.open = true;
+  }
+  activate(node);
+  if (node) node.scrollIntoView({ block: "nearest" });
+}));
+"##;
+
+fn write_html_node(
+    node: Node,
+    field: Option<&str>,
+    id_map: &HashMap<usize, usize>,
+    options: &TreeOptions,
+    output: &mut String,
+) {
+    let mut classes = Vec::new();
+    if !node.is_named() {
+        classes.push("anonymous");
+    }
+    if node.is_error() || node.is_missing() {
+        classes.push("error");
+    }
+    let mut label = String::new();
+    if let Some(field) = field {
+        label.push_str(&format!(
+            "<span class=\"field\">{}:</span> ",
+            escape_html(field)
+        ));
+    }
+    if node.is_missing() {
+        label.push_str("MISSING ");
+    }
+    label.push_str(&escape_html(node.kind()));
+    label.push_str(&format!(
+        " <span class=\"range\">{}-{}</span>",
+        node.start_byte(),
+        node.end_byte()
+    ));
+    if let Some(capture_names) = options.captures.as_ref().and_then(|c| c.get(&node.id())) {
+        for capture_name in capture_names {
+            label.push_str(&format!(
+                " <span class=\"capture\">@{}</span>",
+                escape_html(capture_name)
+            ));
+        }
+    }
+    let mut attributes = format!(
+        "id=\"node_{}\" data-start=\"{}\" data-end=\"{}\"",
+        id_map[&node.id()],
+        node.start_byte(),
+        node.end_byte()
+    );
+    if !classes.is_empty() {
+        attributes.push_str(&format!(" class=\"{}\"", classes.join(" ")));
+    }
+
+    let mut children = Vec::new();
+    let mut cursor = node.walk();
+    if cursor.goto_first_child() {
+        loop {
+            let child = cursor.node();
+            if is_output_node(child, !options.include_anonymous) {
+                children.push((child, cursor.field_name()));
+            }
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+    if children.is_empty() {
+        output.push_str(&format!("<div {}>{}</div>\n", attributes, label));
+        return;
+    }
+    output.push_str(&format!(
+        "<details open>\n<summary {}>{}</summary>\n",
+        attributes, label
+    ));
+    for (child, field) in children {
+        write_html_node(child, field, id_map, options, output);
+    }
+    output.push_str("</details>\n");
+}
+
+/// The source split at every node boundary into `<span data-start="…">` elements, so a byte range can be
+/// highlighted by its spans.
+fn source_to_html(tree: &Tree, code: &str) -> String {
+    let mut boundaries = vec![0, code.len()];
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        boundaries.push(node.start_byte());
+        boundaries.push(node.end_byte());
+        if cursor.goto_first_child() || cursor.goto_next_sibling() {
+            continue;
+        }
+        let mut finished = true;
+        while cursor.goto_parent() {
+            if cursor.goto_next_sibling() {
+                finished = false;
+                break;
+            }
+        }
+        if finished {
+            break;
+        }
+    }
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    boundaries
+        .windows(2)
+        .map(|window| {
+            format!(
+                "<span data-start=\"{}\">{}</span>",
+                window[0],
+                escape_html(&code[window[0]..window[1]])
+            )
+        })
+        .collect()
+}
+
+/// A self-contained HTML page with the source on the left and the collapsible tree on the right, where hovering
+/// a node highlights its range in the source and hovering the source highlights the smallest node there.
+pub fn generate_html(tree: &Tree, code: &str, options: &TreeOptions) -> String {
+    let root = tree.root_node();
+    let id_map = stable_node_ids(root);
+    let mut tree_html = String::new();
+    write_html_node(root, None, &id_map, options, &mut tree_html);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n\
+         <pre id=\"source\">{}</pre>\n<div id=\"tree\">\n{}</div>\n<script>\n{}</script>\n</body>\n</html>\n",
+        escape_html(root.kind()),
+        HTML_STYLE,
+        source_to_html(tree, code),
+        tree_html,
+        HTML_SCRIPT
+    )
+}
+
+/// Outputs the whole tree in one of the `TREE_FORMATS`.
+pub fn process_tree<W>(format: &str, tree: &Tree, code: &str, options: &TreeOptions, writer: &mut W)
+where
+    W: Write,
+{
+    let output = match format {
+        "sexp" => generate_sexp(tree, options),
+        "json" => format!("{}\n", generate_json(tree, code, options)),
+        "elisp" => format!("{}\n", json_to_elisp(&generate_json(tree, code, options))),
+        "html" => generate_html(tree, code, options),
+        _ => unreachable!("{} is not generated from the tree options", format),
+    };
+    write!(writer, "{}", output).expect("write should succeed");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::languages::tests::run_with_args;
+    use serde_json::Value;
+
+    #[test]
+    fn test_sexp() {
+        assert_eq!(
+            run_with_args("fn main() {}", "rust", vec!["--tree", "sexp"]),
+            "(source_file\n  (function_item\n    (identifier)\n    (parameters)\n    (block)))\n"
+        );
+        assert_eq!(
+            run_with_args(
+                "fn main() {}",
+                "rust",
+                vec!["--tree", "sexp", "--tree-fields"]
+            ),
+            "(source_file\n  (function_item\n    name: (identifier)\n    parameters: (parameters)\n    body: (block)))\n"
+        );
+    }
+
+    #[test]
+    fn test_sexp_anonymous_and_ranges() {
+        assert_eq!(
+            run_with_args(
+                "x = 1\ny",
+                "python",
+                vec!["--tree", "sexp", "--tree-ranges", "--include-anonymous"]
+            ),
+            r#"(module 0 7 [0, 0] - [1, 1]
+  (expression_statement 0 5 [0, 0] - [0, 5]
+    (assignment 0 5 [0, 0] - [0, 5]
+      (identifier 0 1 [0, 0] - [0, 1])
+      ("=" 2 3 [0, 2] - [0, 3])
+      (integer 4 5 [0, 4] - [0, 5])))
+  (expression_statement 6 7 [1, 0] - [1, 1]
+    (identifier 6 7 [1, 0] - [1, 1])))
+"#
+        );
+        assert_eq!(
+            run_with_args(
+                "fn main() {\n    let x = 1\n}",
+                "rust",
+                vec!["--tree", "sexp", "--include-anonymous"]
+            )
+            .lines()
+            .filter(|line| line.contains("MISSING"))
+            .collect::<Vec<_>>(),
+            vec!["        (MISSING \";\"))"]
+        );
+        assert_eq!(
+            run_with_args("fn main() {\n    let x = 1\n}", "rust", vec!["--tree", "sexp"]),
+            "(source_file\n  (function_item\n    (identifier)\n    (parameters)\n    (block\n      (let_declaration\n        (identifier)\n        (integer_literal)\n        (MISSING \";\")))))\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let output = run_with_args(
+            "x = 1",
+            "python",
+            vec!["--tree", "json", "--tree-text", "--include-anonymous"],
+        );
+        let root: Value = serde_json::from_str(&output).expect("Output should be valid JSON");
+        assert_eq!(root["id"], 1);
+        assert_eq!(root["kind"], "module");
+        assert_eq!(root["field"], Value::Null);
+        assert_eq!(root["error"], false);
+        assert_eq!(root.get("text"), None);
+        let assignment = &root["children"][0]["children"][0];
+        assert_eq!(assignment["id"], 3);
+        assert_eq!(assignment["kind"], "assignment");
+        assert!(assignment["kind_id"].as_u64().unwrap() > 0);
+        let children = assignment["children"].as_array().unwrap();
+        assert_eq!(children[0]["field"], "left");
+        assert_eq!(children[0]["text"], "x");
+        assert_eq!(children[1]["kind"], "=");
+        assert_eq!(children[1]["named"], false);
+        assert_eq!(children[2]["id"], 6);
+        assert_eq!(children[2]["range"]["start"], 4);
+        assert_eq!(children[2]["range"]["end_point"]["column"], 5);
+
+        // the IDs are the same as in the graphviz dot graph
+        let graphviz = run_with_args("x = 1", "python", vec!["--graphviz-only"]);
+        assert!(graphviz.contains("node_6[label=\"integer 4 5\n1\"];\n"));
+    }
+
+    #[test]
+    fn test_elisp() {
+        let output = run_with_args(
+            "x = \"a\\\"b\"",
+            "python",
+            vec!["--tree", "elisp", "--tree-text"],
+        );
+        assert!(output.starts_with("(:children ((:children ((:children ((:children () :error nil :field \"left\" :id 4 :kind \"identifier\" :kind-id "));
+        assert!(output.contains(" :missing nil :named t :range (:end 1 :end-point (:column 1 :row 0) :start 0 :start-point (:column 0 :row 0)) :text \"x\")"));
+        // the escape sequence \" is escaped again for the Emacs Lisp reader
+        assert!(output.contains(":kind \"escape_sequence\" :kind-id "));
+        assert!(output.contains(":text \"\\\\\\\"\")"));
+        assert!(output.contains(":id 1 :kind \"module\" :kind-id "));
+        assert!(output.ends_with(":range (:end 10 :end-point (:column 10 :row 0) :start 0 :start-point (:column 0 :row 0)))\n"));
+    }
+
+    #[test]
+    fn test_html() {
+        let output = run_with_args(
+            "x = \"<😄>\"",
+            "python",
+            vec!["--tree", "html", "--highlights", "(string) @string"],
+        );
+        assert!(output.starts_with("<!DOCTYPE html>\n"));
+        // no network resources
+        assert!(!output.contains("http"));
+        assert!(output.contains(
+            "<pre id=\"source\"><span data-start=\"0\">x</span><span data-start=\"1\"> </span>"
+        ));
+        assert!(output.contains("<span data-start=\"5\">&lt;😄&gt;</span>"));
+        assert!(output.contains(
+            "<summary id=\"node_1\" data-start=\"0\" data-end=\"12\">module <span class=\"range\">0-12</span></summary>"
+        ));
+        assert!(output.contains(
+            "<div id=\"node_4\" data-start=\"0\" data-end=\"1\"><span class=\"field\">left:</span> identifier <span class=\"range\">0-1</span></div>"
+        ));
+        assert!(output.contains(
+            "<span class=\"field\">right:</span> string <span class=\"range\">4-12</span> <span class=\"capture\">@string</span></summary>"
+        ));
+
+        // anonymous and error nodes are styled
+        let output = run_with_args(
+            "x = )",
+            "python",
+            vec!["--tree", "html", "--include-anonymous"],
+        );
+        assert!(output
+            .contains("<div id=\"node_4\" data-start=\"2\" data-end=\"3\" class=\"anonymous\">= "));
+        assert!(output.contains("class=\"error\">ERROR "));
+        // the query of the grammar crate is only used where a query is required
+        assert!(!output.contains("class=\"capture\""));
+    }
+
+    #[test]
+    fn test_missing_nodes_without_anonymous() {
+        // the missing ; is anonymous, but kept like in the sexp output
+        let code = "fn main() {\n    let x = 1\n}";
+        let output = run_with_args(code, "rust", vec!["--tree", "json"]);
+        let root: Value = serde_json::from_str(&output).expect("Output should be valid JSON");
+        let let_declaration = &root["children"][0]["children"][2]["children"][0];
+        assert_eq!(let_declaration["kind"], "let_declaration");
+        let missing = &let_declaration["children"][2];
+        assert_eq!(missing["kind"], ";");
+        assert_eq!(missing["missing"], true);
+        assert_eq!(missing["named"], false);
+        let output = run_with_args(code, "rust", vec!["--tree", "html"]);
+        assert!(output.contains("class=\"anonymous error\">MISSING ; "));
+    }
+}